@@ -0,0 +1,296 @@
+use crate::object::{Object, SanitizeReport};
+use crate::renderer::Vertex;
+use crate::{binding, globals, light, panorama, renderer, texture};
+use anyhow::*;
+use cgmath::InnerSpace;
+use wgpu::util::DeviceExt;
+
+// What is drawn behind the models
+pub enum Background {
+    // plain color the targets are cleared to
+    Color(wgpu::Color),
+    // vertical gradient over the view, from the top color to the bottom one
+    Gradient {
+        top: wgpu::Color,
+        bottom: wgpu::Color,
+    },
+    // cube map faces in the +X, -X, +Y, -Y, +Z, -Z order
+    Skybox(Box<[image::DynamicImage; 6]>),
+    // clear sky with the sun in the given direction, which can't be zero
+    Sky {
+        sun_direction: cgmath::Vector3<f32>,
+    },
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Color(wgpu::Color {
+            r: 0.1,
+            g: 0.2,
+            b: 0.3,
+            a: 1.0,
+        })
+    }
+}
+
+impl Background {
+    // Sky and ground colors of the ambient light, taken from the colors above
+    // and below the horizon. None for backgrounds without a horizon.
+    pub(crate) fn ambient(&self) -> Option<([f32; 3], [f32; 3])> {
+        let (sky, ground) = match self {
+            Background::Color(_) | Background::Skybox(_) => return None,
+            Background::Gradient { top, bottom } => {
+                let rgb = |color: &wgpu::Color| [color.r as f32, color.g as f32, color.b as f32];
+                (rgb(top), rgb(bottom))
+            }
+            // average of the upper half of the sky shader, and its ground
+            Background::Sky { .. } => ([0.35, 0.525, 0.8], [0.3, 0.28, 0.25]),
+        };
+        let scale = |color: [f32; 3]| color.map(|channel| channel * light::AMBIENT_STRENGTH);
+        Some((scale(sky), scale(ground)))
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct BackgroundUniform {
+    mode: u32,
+    _padding: [u32; 3],
+    top: [f32; 4],
+    bottom: [f32; 4],
+    sun_direction: [f32; 4],
+}
+
+fn color_array(color: wgpu::Color) -> [f32; 4] {
+    [
+        color.r as f32,
+        color.g as f32,
+        color.b as f32,
+        color.a as f32,
+    ]
+}
+
+// Normalized sun direction of the sky. A zero or non finite direction has no
+// sun, the shader would turn the whole sky to NaN.
+fn sun_uniform(sun_direction: cgmath::Vector3<f32>) -> Result<[f32; 4]> {
+    let length = sun_direction.magnitude();
+    ensure!(
+        length.is_normal(),
+        "sun direction {:?} can't be normalized",
+        sun_direction
+    );
+    Ok((sun_direction / length).extend(0.0).into())
+}
+
+// Draws the background at the start of the first render pass of a view
+pub struct BackgroundRenderer {
+    // color the targets are cleared to before the background is drawn
+    pub clear_color: wgpu::Color,
+    // None when the clear color is the whole background
+    pipeline: Option<wgpu::RenderPipeline>,
+    bind_group: wgpu::BindGroup,
+    sphere: renderer::Model,
+}
+
+impl BackgroundRenderer {
+    pub fn new(background: Background, inputs: &renderer::PipelineInputs) -> Result<Self> {
+        let (device, queue) = (inputs.device.as_ref(), inputs.queue);
+        let black = wgpu::Color::BLACK;
+        let mut uniform = BackgroundUniform {
+            mode: 0,
+            _padding: [0; 3],
+            top: color_array(black),
+            bottom: color_array(black),
+            sun_direction: [0.0, 1.0, 0.0, 0.0],
+        };
+        let mut faces = None;
+        let clear_color = match background {
+            Background::Color(color) => color,
+            Background::Gradient { top, bottom } => {
+                uniform.mode = 1;
+                uniform.top = color_array(top);
+                uniform.bottom = color_array(bottom);
+                black
+            }
+            Background::Skybox(images) => {
+                uniform.mode = 2;
+                faces = Some(images);
+                black
+            }
+            Background::Sky { sun_direction } => {
+                uniform.mode = 3;
+                uniform.sun_direction = sun_uniform(sun_direction)?;
+                black
+            }
+        };
+
+        // other modes than the skybox sample a black placeholder face
+        let faces = faces.unwrap_or_else(|| {
+            Box::new([(); 6].map(|_| {
+                image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+                    1,
+                    1,
+                    image::Rgba([0, 0, 0, 255]),
+                ))
+            }))
+        });
+        let skybox = texture::Texture::cube_from_images(device, queue, &faces, Some("skybox"))?;
+
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Background Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                        filtering: true,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("background_bind_group_layout"),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&skybox.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&skybox.sampler),
+                },
+            ],
+            label: Some("background_bind_group"),
+        });
+
+        let pipeline = if uniform.mode == 0 {
+            None
+        } else {
+            // the background parameters take the place of the material
+            let layout = inputs.registry.pipeline_layout(
+                device,
+                Some("Background Pipeline Layout"),
+                [
+                    Some(&bind_group_layout),
+                    Some(&inputs.camera.bind_group_layout),
+                    None,
+                    Some(&inputs.globals.bind_group_layout),
+                ],
+            );
+            Some(renderer::ModelRenderer::create_render_pipeline(
+                device,
+                &layout,
+                inputs.color_format,
+                Some(inputs.depth_format),
+                &[renderer::ModelVertex::desc()],
+                wgpu::ShaderModuleDescriptor {
+                    label: Some("Background Shader"),
+                    source: wgpu::ShaderSource::Wgsl(
+                        globals::preprocess(include_str!("shader/background.wgsl")).into(),
+                    ),
+                },
+            ))
+        };
+
+        let sphere = renderer::Model::load(
+            device,
+            queue,
+            Object {
+                models: vec![tobj::Model::new(
+                    panorama::inward_sphere(),
+                    "background".into(),
+                )],
+                textures: None,
+                sanitize_report: SanitizeReport::default(),
+            },
+        )?;
+
+        Ok(Self {
+            clear_color,
+            pipeline,
+            bind_group,
+            sphere,
+        })
+    }
+
+    // bind_groups are the scene groups, the background group replacing group 0
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        bind_groups: &[&'a wgpu::BindGroup],
+    ) {
+        let pipeline = match self.pipeline.as_ref() {
+            Some(pipeline) => pipeline,
+            None => return,
+        };
+        render_pass.set_pipeline(pipeline);
+        for (index, group) in bind_groups.iter().enumerate() {
+            if index as u32 == binding::MATERIAL_GROUP {
+                render_pass.set_bind_group(index as u32, &self.bind_group, &[]);
+            } else {
+                render_pass.set_bind_group(index as u32, group, &[]);
+            }
+        }
+        for mesh in &self.sphere.meshes {
+            render_pass.set_vertex_buffer(0, mesh.geometry.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(
+                mesh.geometry.index_buffer.slice(..),
+                wgpu::IndexFormat::Uint32,
+            );
+            render_pass.draw_indexed(0..mesh.geometry.num_elements, 0, 0..1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::globals::tests::validate_wgsl;
+    use cgmath::Vector3;
+
+    #[test]
+    fn sun_direction_is_normalized() {
+        assert_eq!(
+            sun_uniform(Vector3::new(0.0, 3.0, 4.0)).unwrap(),
+            [0.0, 0.6, 0.8, 0.0]
+        );
+        assert!(sun_uniform(Vector3::new(0.0, 0.0, 0.0)).is_err());
+        assert!(sun_uniform(Vector3::new(f32::NAN, 1.0, 0.0)).is_err());
+    }
+
+    #[test]
+    fn shader_is_valid() {
+        validate_wgsl(include_str!("shader/background.wgsl"));
+    }
+}
@@ -0,0 +1,501 @@
+use crate::bvh::TriangleBvh;
+use crate::camera::Ray;
+use crate::jobs;
+use anyhow::*;
+use cgmath::*;
+
+#[derive(Debug, Copy, Clone)]
+pub struct BakeOptions {
+    // size in texels of the square maps
+    pub resolution: u32,
+    // farthest the high poly surface can be from the low poly one, on both sides
+    pub max_distance: f32,
+    // rays cast over the hemisphere of each texel for the occlusion
+    pub ao_samples: u32,
+    // geometry farther than this doesn't occlude
+    pub ao_distance: f32,
+}
+
+impl Default for BakeOptions {
+    fn default() -> Self {
+        Self {
+            resolution: 512,
+            max_distance: 0.1,
+            ao_samples: 64,
+            ao_distance: 1.0,
+        }
+    }
+}
+
+// Maps baked in the texture coordinates of the low poly mesh
+pub struct BakedMaps {
+    // 255 where nothing occludes the surface
+    pub ambient_occlusion: image::GrayImage,
+    // tangent space normals, the tangent along +u and the bitangent along +v
+    pub normals: image::RgbaImage,
+}
+
+// Point of the low poly surface seen by a texel, with its tangent frame
+struct Texel {
+    index: usize,
+    position: Point3<f32>,
+    tangent: Vector3<f32>,
+    bitangent: Vector3<f32>,
+    normal: Vector3<f32>,
+}
+
+fn vertex(values: &[f32], i: u32) -> Vector3<f32> {
+    let i = i as usize * 3;
+    Vector3::new(values[i], values[i + 1], values[i + 2])
+}
+
+// Normal of a mesh at a point of one of its triangles, interpolated from the
+// vertex normals when there are some
+fn surface_normal(mesh: &tobj::Mesh, triangle: usize, weights: Vector3<f32>) -> Vector3<f32> {
+    let t = &mesh.indices[triangle * 3..triangle * 3 + 3];
+    let normal = if mesh.normals.len() == mesh.positions.len() {
+        vertex(&mesh.normals, t[0]) * weights.x
+            + vertex(&mesh.normals, t[1]) * weights.y
+            + vertex(&mesh.normals, t[2]) * weights.z
+    } else {
+        let p = [t[0], t[1], t[2]].map(|i| vertex(&mesh.positions, i));
+        (p[1] - p[0]).cross(p[2] - p[0])
+    };
+    normal.normalize()
+}
+
+// Texels covered by the triangles of the mesh in the given uv set
+fn rasterize(mesh: &tobj::Mesh, uvs: &[f32], resolution: u32) -> Vec<Texel> {
+    let size = resolution as f32;
+    let mut texels = Vec::new();
+    for (triangle, t) in mesh.indices.chunks_exact(3).enumerate() {
+        let p = [t[0], t[1], t[2]].map(|i| vertex(&mesh.positions, i));
+        let uv = [t[0], t[1], t[2]].map(|i| {
+            let i = i as usize * 2;
+            Vector2::new(uvs[i], uvs[i + 1])
+        });
+
+        // tangent and bitangent following the texture coordinates
+        let (duv1, duv2) = (uv[1] - uv[0], uv[2] - uv[0]);
+        let area = duv1.perp_dot(duv2);
+        if area.abs() < f32::EPSILON {
+            continue;
+        }
+        let (dp1, dp2) = (p[1] - p[0], p[2] - p[0]);
+        let tangent = (dp1 * duv2.y - dp2 * duv1.y) / area;
+        let bitangent = (dp2 * duv1.x - dp1 * duv2.x) / area;
+
+        let texel_uv = uv.map(|uv| uv * size);
+        let min = texel_uv[1..].iter().fold(texel_uv[0], |min, uv| {
+            Vector2::new(min.x.min(uv.x), min.y.min(uv.y))
+        });
+        let max = texel_uv[1..].iter().fold(texel_uv[0], |max, uv| {
+            Vector2::new(max.x.max(uv.x), max.y.max(uv.y))
+        });
+        let area = (texel_uv[1] - texel_uv[0]).perp_dot(texel_uv[2] - texel_uv[0]);
+        let (x0, x1) = (min.x.floor().max(0.0) as u32, max.x.ceil().min(size) as u32);
+        let (y0, y1) = (min.y.floor().max(0.0) as u32, max.y.ceil().min(size) as u32);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let center = Vector2::new(x as f32 + 0.5, y as f32 + 0.5);
+                let edge = |a: usize, b: usize| {
+                    (texel_uv[b] - texel_uv[a]).perp_dot(center - texel_uv[a]) / area
+                };
+                let weights = Vector3::new(edge(1, 2), edge(2, 0), edge(0, 1));
+                if weights.x < 0.0 || weights.y < 0.0 || weights.z < 0.0 {
+                    continue;
+                }
+
+                let normal = surface_normal(mesh, triangle, weights);
+                let tangent = (tangent - normal * normal.dot(tangent)).normalize();
+                let handedness = normal.cross(tangent).dot(bitangent).signum();
+                texels.push(Texel {
+                    index: (y * resolution + x) as usize,
+                    position: Point3::from_vec(
+                        p[0] * weights.x + p[1] * weights.y + p[2] * weights.z,
+                    ),
+                    tangent,
+                    bitangent: normal.cross(tangent) * handedness,
+                    normal,
+                });
+            }
+        }
+    }
+    texels
+}
+
+// Cosine weighted directions over the hemisphere around +z, spread by a
+// Hammersley sequence so that bakes are the same from one run to the other
+fn hemisphere(samples: u32) -> Vec<Vector3<f32>> {
+    (0..samples.max(1))
+        .map(|i| {
+            let u = (i as f32 + 0.5) / samples.max(1) as f32;
+            let phi = std::f32::consts::TAU * (i.reverse_bits() as f32 / 2f32.powi(32));
+            let r = u.sqrt();
+            Vector3::new(r * phi.cos(), r * phi.sin(), (1.0 - u).sqrt())
+        })
+        .collect()
+}
+
+// Two directions orthogonal to the normal and to each other
+fn frame(normal: Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    let side = if normal.x.abs() < 0.9 {
+        Vector3::unit_x()
+    } else {
+        Vector3::unit_y()
+    };
+    let u = normal.cross(side).normalize();
+    (u, normal.cross(u))
+}
+
+// Bake the ambient occlusion and the normals of a high poly mesh into the
+// texture coordinates of a low poly one, e.g. given by unwrap_uvs. Each texel
+// looks for the high poly surface along the low poly normal.
+pub fn bake_maps(low: &tobj::Mesh, high: &tobj::Mesh, options: BakeOptions) -> Result<BakedMaps> {
+    ensure!(
+        low.texcoords.len() * 3 == low.positions.len() * 2,
+        "the low poly mesh has no texture coordinates to bake into"
+    );
+    let resolution = options.resolution.max(1);
+    let bvh = TriangleBvh::new(high);
+    let directions = hemisphere(options.ao_samples);
+    // keeps the rays from hitting the surface they start from
+    let bias = options.max_distance.max(options.ao_distance) * 1e-4;
+
+    let texels = rasterize(low, &low.texcoords, resolution);
+    let baked = jobs::map_jobs(texels.chunks(1024).collect(), |chunk| {
+        chunk
+            .iter()
+            .map(|texel| {
+                // cast from outside of the low poly surface inward, to the first
+                // high poly surface met
+                let ray = Ray {
+                    origin: texel.position + texel.normal * options.max_distance,
+                    direction: -texel.normal,
+                };
+                let (position, normal) = match bvh.intersect(&ray, options.max_distance * 2.0) {
+                    Some(hit) => {
+                        let weights = Vector3::new(
+                            1.0 - hit.barycentric.x - hit.barycentric.y,
+                            hit.barycentric.x,
+                            hit.barycentric.y,
+                        );
+                        let normal = surface_normal(high, hit.triangle, weights);
+                        (ray.at(hit.distance), normal)
+                    }
+                    None => (texel.position, texel.normal),
+                };
+
+                let tangent_normal = Vector3::new(
+                    texel.tangent.dot(normal),
+                    texel.bitangent.dot(normal),
+                    texel.normal.dot(normal),
+                );
+
+                // frame around the high poly normal for the hemisphere rays
+                let (u, v) = frame(normal);
+                let origin = position + normal * bias;
+                let occluded = directions
+                    .iter()
+                    .filter(|d| {
+                        let ray = Ray {
+                            origin,
+                            direction: u * d.x + v * d.y + normal * d.z,
+                        };
+                        bvh.intersect(&ray, options.ao_distance).is_some()
+                    })
+                    .count();
+                let ao = 1.0 - occluded as f32 / directions.len() as f32;
+
+                (texel.index, ao, tangent_normal)
+            })
+            .collect::<Vec<_>>()
+    });
+
+    let to_byte = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    let mut ambient_occlusion =
+        image::GrayImage::from_pixel(resolution, resolution, image::Luma([255]));
+    let mut normals =
+        image::RgbaImage::from_pixel(resolution, resolution, image::Rgba([128, 128, 255, 255]));
+    for (index, ao, normal) in baked.into_iter().flatten() {
+        let (x, y) = (index as u32 % resolution, index as u32 / resolution);
+        ambient_occlusion.put_pixel(x, y, image::Luma([to_byte(ao)]));
+        let encoded = normal * 0.5 + Vector3::new(0.5, 0.5, 0.5);
+        normals.put_pixel(
+            x,
+            y,
+            image::Rgba([
+                to_byte(encoded.x),
+                to_byte(encoded.y),
+                to_byte(encoded.z),
+                255,
+            ]),
+        );
+    }
+
+    Ok(BakedMaps {
+        ambient_occlusion,
+        normals,
+    })
+}
+
+// Point light of a lightmap bake, lighting as the default shading does: the
+// color scaled by the cosine of the incidence, without falloff
+#[derive(Debug, Copy, Clone)]
+pub struct BakeLight {
+    pub position: Point3<f32>,
+    pub color: Vector3<f32>,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct LightmapOptions {
+    // size in texels of the square lightmap
+    pub resolution: u32,
+    // paths traced per texel for the indirect light
+    pub samples: u32,
+    // times light bounces off the surfaces, 0 for direct light only
+    pub bounces: u32,
+    // share of the light the surfaces reflect
+    pub albedo: f32,
+    // light of the paths leaving the scene
+    pub sky_color: Vector3<f32>,
+}
+
+impl Default for LightmapOptions {
+    fn default() -> Self {
+        Self {
+            resolution: 256,
+            samples: 64,
+            bounces: 2,
+            albedo: 0.5,
+            sky_color: Vector3::zero(),
+        }
+    }
+}
+
+// Xorshift generator, seeded per texel so that bakes are reproducible
+struct Random(u32);
+
+impl Random {
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+struct Tracer<'a> {
+    mesh: &'a tobj::Mesh,
+    bvh: TriangleBvh,
+    lights: &'a [BakeLight],
+    options: LightmapOptions,
+    bias: f32,
+}
+
+impl<'a> Tracer<'a> {
+    fn direct(&self, position: Point3<f32>, normal: Vector3<f32>) -> Vector3<f32> {
+        let origin = position + normal * self.bias;
+        self.lights
+            .iter()
+            .map(|light| {
+                let to_light = light.position - origin;
+                let distance = to_light.magnitude();
+                let cosine = normal.dot(to_light) / distance;
+                let ray = Ray {
+                    origin,
+                    direction: to_light / distance,
+                };
+                if cosine > 0.0 && self.bvh.intersect(&ray, distance).is_none() {
+                    light.color * cosine
+                } else {
+                    Vector3::zero()
+                }
+            })
+            .sum()
+    }
+
+    // Light coming back along the ray, after the given number of bounces
+    fn trace(&self, ray: Ray, bounces: u32, random: &mut Random) -> Vector3<f32> {
+        let hit = match self.bvh.intersect(&ray, f32::INFINITY) {
+            Some(hit) => hit,
+            None => return self.options.sky_color,
+        };
+        let weights = Vector3::new(
+            1.0 - hit.barycentric.x - hit.barycentric.y,
+            hit.barycentric.x,
+            hit.barycentric.y,
+        );
+        let position = ray.at(hit.distance);
+        let mut normal = surface_normal(self.mesh, hit.triangle, weights);
+        if normal.dot(ray.direction) > 0.0 {
+            normal = -normal;
+        }
+
+        let mut light = self.direct(position, normal);
+        if bounces > 1 {
+            let (u, v) = frame(normal);
+            let (u1, u2) = (random.next(), random.next());
+            let (r, phi) = (u1.sqrt(), std::f32::consts::TAU * u2);
+            let ray = Ray {
+                origin: position + normal * self.bias,
+                direction: u * (r * phi.cos()) + v * (r * phi.sin()) + normal * (1.0 - u1).sqrt(),
+            };
+            light += self.trace(ray, bounces - 1, random);
+        }
+        light * self.options.albedo
+    }
+}
+
+fn to_srgb(linear: f32) -> u8 {
+    let linear = linear.clamp(0.0, 1.0);
+    let srgb = if linear <= 0.0031308 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    };
+    (srgb * 255.0).round() as u8
+}
+
+// Bake the light the surfaces of a static mesh receive into its lightmap uvs,
+// e.g. an atlas of unwrap_uvs for the merged static geometry of a scene. The mesh
+// shadows itself and bounces light with a path tracer. The lightmap is sRGB
+// encoded like the textures lens loads, for the shading to multiply with the
+// surface colors.
+pub fn bake_lightmap(
+    atlas: &crate::UvAtlas,
+    lights: &[BakeLight],
+    options: LightmapOptions,
+) -> image::RgbaImage {
+    let mesh = &atlas.mesh;
+    let resolution = options.resolution.max(1);
+    let bounds = crate::Aabb::from_points(
+        mesh.positions
+            .chunks_exact(3)
+            .map(|p| Point3::new(p[0], p[1], p[2])),
+    );
+    let tracer = Tracer {
+        mesh,
+        bvh: TriangleBvh::new(mesh),
+        lights,
+        options,
+        bias: bounds.radius().max(f32::EPSILON) * 1e-4,
+    };
+    // stratified first bounce, randomly continued paths
+    let directions = hemisphere(options.samples);
+
+    let texels = rasterize(mesh, &atlas.lightmap_uvs, resolution);
+    let baked = jobs::map_jobs(texels.chunks(256).collect(), |chunk| {
+        chunk
+            .iter()
+            .map(|texel| {
+                let mut light = tracer.direct(texel.position, texel.normal);
+                if options.bounces > 0 {
+                    let mut random = Random((texel.index as u32).wrapping_mul(9781) | 1);
+                    let (u, v) = frame(texel.normal);
+                    let origin = texel.position + texel.normal * tracer.bias;
+                    let indirect: Vector3<f32> = directions
+                        .iter()
+                        .map(|d| {
+                            let ray = Ray {
+                                origin,
+                                direction: u * d.x + v * d.y + texel.normal * d.z,
+                            };
+                            tracer.trace(ray, options.bounces, &mut random)
+                        })
+                        .sum();
+                    light += indirect / directions.len() as f32;
+                }
+                (texel.index, light)
+            })
+            .collect::<Vec<_>>()
+    });
+
+    let mut lightmap = vec![None; (resolution * resolution) as usize];
+    for (index, light) in baked.into_iter().flatten() {
+        lightmap[index] = Some(light);
+    }
+    // spread the border texels over the empty ones around the charts, so that
+    // filtering doesn't blend in black
+    for _ in 0..2 {
+        let previous = lightmap.clone();
+        for (index, texel) in lightmap.iter_mut().enumerate() {
+            if texel.is_some() {
+                continue;
+            }
+            let (x, y) = (
+                (index as u32 % resolution) as i64,
+                (index as u32 / resolution) as i64,
+            );
+            let neighbours: Vec<Vector3<f32>> = [(-1, 0), (1, 0), (0, -1), (0, 1)]
+                .iter()
+                .filter_map(|(dx, dy)| {
+                    let (x, y) = (x + dx, y + dy);
+                    let inside =
+                        (0..resolution as i64).contains(&x) && (0..resolution as i64).contains(&y);
+                    inside
+                        .then(|| previous[(y * resolution as i64 + x) as usize])
+                        .flatten()
+                })
+                .collect();
+            if !neighbours.is_empty() {
+                *texel = Some(neighbours.iter().sum::<Vector3<f32>>() / neighbours.len() as f32);
+            }
+        }
+    }
+
+    image::RgbaImage::from_fn(resolution, resolution, |x, y| {
+        let light = lightmap[(y * resolution + x) as usize].unwrap_or_else(Vector3::zero);
+        image::Rgba([to_srgb(light.x), to_srgb(light.y), to_srgb(light.z), 255])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a unit square facing up, its uvs covering the whole texture
+    fn plane() -> tobj::Mesh {
+        tobj::Mesh {
+            positions: vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 1.0, 0.0, 0.0, 1.0],
+            normals: [0.0, 1.0, 0.0].repeat(4),
+            texcoords: vec![0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0],
+            indices: vec![0, 2, 1, 0, 3, 2],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn flat_plane_bakes_flat_maps() {
+        let options = BakeOptions {
+            resolution: 16,
+            ao_samples: 32,
+            ..Default::default()
+        };
+        let maps = bake_maps(&plane(), &plane(), options).unwrap();
+        // every texel is baked, not left at the defaults
+        let mut baked: Vec<usize> = rasterize(&plane(), &plane().texcoords, 16)
+            .iter()
+            .map(|texel| texel.index)
+            .collect();
+        baked.sort_unstable();
+        baked.dedup();
+        assert_eq!(baked.len(), 16 * 16);
+        // nothing is occluded and the normals are those of the low poly surface
+        for pixel in maps.ambient_occlusion.pixels() {
+            assert_eq!(pixel.0, [255]);
+        }
+        for pixel in maps.normals.pixels() {
+            assert_eq!(pixel.0, [128, 128, 255, 255]);
+        }
+    }
+
+    #[test]
+    fn bake_needs_texture_coordinates() {
+        let low = tobj::Mesh {
+            texcoords: Vec::new(),
+            ..plane()
+        };
+        assert!(bake_maps(&low, &plane(), BakeOptions::default()).is_err());
+    }
+}
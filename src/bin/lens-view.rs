@@ -0,0 +1,81 @@
+// Opens a model file in a lens window:
+//   lens-view <model.obj> [--hdri <image>] [--record <file>] [--play <file>]
+// The camera orbits around the model with the mouse and zooms with the wheel.
+// The hdri is an equirectangular image shown around the model. Input can be
+// recorded to a file and played back later. A right click logs the color
+// under the cursor.
+//   F  frames the model again
+//   V  goes through the shaded, depth, normals, wireframe and overdraw views
+//   H  hides or shows the hdri
+//   P  saves a screenshot to lens-screenshot-<n>.png
+//   Q  goes through the quality presets
+//   T  pauses or resumes the light
+
+use anyhow::*;
+
+const USAGE: &str =
+    "usage: lens-view <model.obj> [--hdri <image>] [--record <file>] [--play <file>]";
+
+fn main() -> Result<()> {
+    // picked colors, screenshots and the scene stats are logged at the info level
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let mut model = None;
+    let mut hdri = None;
+    let mut record = None;
+    let mut play = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = match arg.as_str() {
+            "--hdri" => &mut hdri,
+            "--record" => &mut record,
+            "--play" => &mut play,
+            _ if model.is_none() && !arg.starts_with("--") => {
+                model = Some(arg);
+                continue;
+            }
+            _ => bail!("unexpected argument {:?}\n{}", arg, USAGE),
+        };
+        *value = Some(
+            args.next()
+                .with_context(|| format!("missing value of {}\n{}", arg, USAGE))?,
+        );
+    }
+    let model = model.context(USAGE)?;
+
+    let mut lens = lens::Lens::new();
+    lens.set_orbit(true);
+    lens.add_object(lens::LensObject {
+        object: lens::Object::load_from(&model),
+        position: cgmath::Vector3::new(0.0, 0.0, 0.0),
+        transform: None,
+        shader_file: include_str!("../shader/thumbnail.wgsl"),
+        instances: None,
+    });
+    if let Some(hdri) = hdri {
+        lens.set_environment(image::open(&hdri).with_context(|| format!("can't open {}", hdri))?);
+    }
+    if let Some(record) = record {
+        lens.record_input(&record)
+            .with_context(|| format!("can't create {}", record))?;
+    }
+    if let Some(play) = play {
+        lens.play_input(&play)?;
+    }
+    lens.set_color_picker(winit::event::MouseButton::Right, |picked| {
+        let [r, g, b, a] = picked.color;
+        log::info!(
+            "#{:02x}{:02x}{:02x}{:02x} at {}, {}",
+            r,
+            g,
+            b,
+            a,
+            picked.x,
+            picked.y
+        );
+    });
+    log::info!("{:?}", lens.stats());
+
+    lens.run();
+    Ok(())
+}
@@ -0,0 +1,62 @@
+// Bind group indices shared by every pipeline of lens and by the shaders
+// written for them. Groups are bound at these indices whatever the model, so a
+// shader can rely on them:
+//   0: material, an empty group when the model has no material
+//   1: camera
+//   2: light
+//   3: globals
+pub const MATERIAL_GROUP: u32 = 0;
+pub const CAMERA_GROUP: u32 = 1;
+pub const LIGHT_GROUP: u32 = 2;
+pub const GLOBALS_GROUP: u32 = 3;
+pub const GROUP_COUNT: usize = 4;
+
+// Builds the pipeline layouts following the canonical group indices, and holds
+// the empty group bound in place of the missing ones
+pub struct BindingRegistry {
+    empty_layout: wgpu::BindGroupLayout,
+    pub empty_bind_group: wgpu::BindGroup,
+}
+
+impl BindingRegistry {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let empty_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[],
+            label: Some("empty_bind_group_layout"),
+        });
+        let empty_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &empty_layout,
+            entries: &[],
+            label: Some("empty_bind_group"),
+        });
+
+        Self {
+            empty_layout,
+            empty_bind_group,
+        }
+    }
+
+    // Pipeline layout with each given layout at its group index. The groups
+    // before the last given one are filled with the empty layout.
+    pub fn pipeline_layout(
+        &self,
+        device: &wgpu::Device,
+        label: Option<&str>,
+        layouts: [Option<&wgpu::BindGroupLayout>; GROUP_COUNT],
+    ) -> wgpu::PipelineLayout {
+        let used = layouts
+            .iter()
+            .rposition(|layout| layout.is_some())
+            .map_or(0, |last| last + 1);
+        let bind_group_layouts: Vec<_> = layouts[..used]
+            .iter()
+            .map(|layout| layout.unwrap_or(&self.empty_layout))
+            .collect();
+
+        device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label,
+            bind_group_layouts: &bind_group_layouts[..],
+            push_constant_ranges: &[],
+        })
+    }
+}
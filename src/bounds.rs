@@ -0,0 +1,215 @@
+use crate::camera::Ray;
+use cgmath::*;
+
+// Axis aligned bounding box, stored as its min and max corners
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    // An empty box contains no point: any union with it gives the other box
+    pub fn empty() -> Self {
+        Self {
+            min: Point3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            max: Point3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+        }
+    }
+
+    pub fn from_points<I: IntoIterator<Item = Point3<f32>>>(points: I) -> Self {
+        points.into_iter().fold(Self::empty(), |aabb, point| Self {
+            min: Point3::new(
+                aabb.min.x.min(point.x),
+                aabb.min.y.min(point.y),
+                aabb.min.z.min(point.z),
+            ),
+            max: Point3::new(
+                aabb.max.x.max(point.x),
+                aabb.max.y.max(point.y),
+                aabb.max.z.max(point.z),
+            ),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn union(&self, other: &Aabb) -> Self {
+        Self {
+            min: Point3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: Point3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    pub fn center(&self) -> Point3<f32> {
+        self.min.midpoint(self.max)
+    }
+
+    // Radius of the sphere centered on the box that contains it
+    pub fn radius(&self) -> f32 {
+        (self.max - self.min).magnitude() * 0.5
+    }
+
+    pub fn contains(&self, other: &Aabb) -> bool {
+        self.min.x <= other.min.x
+            && self.min.y <= other.min.y
+            && self.min.z <= other.min.z
+            && self.max.x >= other.max.x
+            && self.max.y >= other.max.y
+            && self.max.z >= other.max.z
+    }
+
+    // Box grown by the margin on every side
+    pub fn expand(&self, margin: f32) -> Self {
+        let margin = Vector3::new(margin, margin, margin);
+        Self {
+            min: self.min - margin,
+            max: self.max + margin,
+        }
+    }
+
+    // Half the area of the faces, to compare the sizes of boxes
+    pub fn surface_area(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let size = self.max - self.min;
+        size.x * size.y + size.y * size.z + size.z * size.x
+    }
+
+    // Box around the transformed corners of this one
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> Self {
+        if self.is_empty() {
+            return *self;
+        }
+        Self::from_points((0..8).map(|corner| {
+            let point = Point3::new(
+                if corner & 1 == 0 {
+                    self.min.x
+                } else {
+                    self.max.x
+                },
+                if corner & 2 == 0 {
+                    self.min.y
+                } else {
+                    self.max.y
+                },
+                if corner & 4 == 0 {
+                    self.min.z
+                } else {
+                    self.max.z
+                },
+            );
+            matrix.transform_point(point)
+        }))
+    }
+
+    // Distance from the point to the box, 0 inside
+    pub fn distance(&self, point: Point3<f32>) -> f32 {
+        let outside = Vector3::new(
+            (self.min.x - point.x).max(point.x - self.max.x).max(0.0),
+            (self.min.y - point.y).max(point.y - self.max.y).max(0.0),
+            (self.min.z - point.z).max(point.z - self.max.z).max(0.0),
+        );
+        outside.magnitude()
+    }
+
+    // Distance along the ray at which it enters the box before max_distance, 0
+    // when it starts inside
+    pub fn ray_distance(&self, ray: &Ray, max_distance: f32) -> Option<f32> {
+        let (mut near, mut far) = (0.0f32, max_distance);
+        for axis in 0..3 {
+            let inverse = 1.0 / ray.direction[axis];
+            let mut t0 = (self.min[axis] - ray.origin[axis]) * inverse;
+            let mut t1 = (self.max[axis] - ray.origin[axis]) * inverse;
+            if inverse < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // NaN when the origin is on a slab of a parallel ray, keep the bounds then
+            near = if t0 > near { t0 } else { near };
+            far = if t1 < far { t1 } else { far };
+            if far < near {
+                return None;
+            }
+        }
+        Some(near)
+    }
+}
+
+// Volume seen by a camera, as the planes bounding it. Points p inside have
+// dot(normal, p) + distance >= 0 for every plane.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Frustum {
+    pub planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    // Planes of a wgpu view projection, whose depths go from 0 to 1
+    pub fn from_view_proj(view_proj: &Matrix4<f32>) -> Self {
+        let row = |i: usize| view_proj.row(i);
+        let planes = [
+            row(3) + row(0),
+            row(3) - row(0),
+            row(3) + row(1),
+            row(3) - row(1),
+            row(2),
+            row(3) - row(2),
+        ];
+        Self {
+            planes: planes.map(|plane| plane / plane.truncate().magnitude()),
+        }
+    }
+
+    // Whether some of the box may be in the frustum. Boxes near the corners of
+    // the frustum can pass without being in it.
+    pub fn intersects(&self, aabb: &Aabb) -> bool {
+        !aabb.is_empty()
+            && self.planes.iter().all(|plane| {
+                // corner of the box the farthest along the plane normal
+                let corner = Vector3::new(
+                    if plane.x >= 0.0 {
+                        aabb.max.x
+                    } else {
+                        aabb.min.x
+                    },
+                    if plane.y >= 0.0 {
+                        aabb.max.y
+                    } else {
+                        aabb.min.y
+                    },
+                    if plane.z >= 0.0 {
+                        aabb.max.z
+                    } else {
+                        aabb.min.z
+                    },
+                );
+                plane.truncate().dot(corner) + plane.w >= 0.0
+            })
+    }
+
+    // The same frustum without its near and far planes, reaching anything in
+    // front of the eye within its sides however close or far. The sides meet
+    // at the eye, so nothing behind it is inside.
+    pub fn sides(&self) -> Self {
+        let mut planes = self.planes;
+        planes[4] = Vector4::new(0.0, 0.0, 0.0, 1.0);
+        planes[5] = Vector4::new(0.0, 0.0, 0.0, 1.0);
+        Self { planes }
+    }
+
+    pub fn contains_sphere(&self, center: Point3<f32>, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.truncate().dot(center.to_vec()) + plane.w >= -radius)
+    }
+}
@@ -0,0 +1,242 @@
+use crate::bounds::Aabb;
+use crate::camera::Ray;
+use cgmath::*;
+
+// triangles per leaf
+const LEAF_SIZE: usize = 4;
+
+// Closest triangle hit by a ray
+#[derive(Debug, Copy, Clone)]
+pub struct Hit {
+    // along the ray, in units of its direction
+    pub distance: f32,
+    // index of the triangle in the mesh
+    pub triangle: usize,
+    // weights of the second and third vertices of the triangle
+    pub barycentric: Vector2<f32>,
+}
+
+struct Node {
+    bounds: Aabb,
+    // leaves hold count triangles from start, other nodes have their first
+    // child next to them and their second one at start
+    start: usize,
+    count: usize,
+}
+
+// Bounding volume hierarchy over the triangles of a mesh, for ray casts
+pub struct TriangleBvh {
+    triangles: Vec<[Point3<f32>; 3]>,
+    // triangle indices in the order of the leaves
+    order: Vec<usize>,
+    nodes: Vec<Node>,
+}
+
+impl TriangleBvh {
+    pub fn new(mesh: &tobj::Mesh) -> Self {
+        let position = |i: u32| {
+            let p = &mesh.positions[i as usize * 3..i as usize * 3 + 3];
+            Point3::new(p[0], p[1], p[2])
+        };
+        let triangles: Vec<[Point3<f32>; 3]> = mesh
+            .indices
+            .chunks_exact(3)
+            .map(|t| [position(t[0]), position(t[1]), position(t[2])])
+            .collect();
+
+        let mut bvh = Self {
+            order: (0..triangles.len()).collect(),
+            triangles,
+            nodes: Vec::new(),
+        };
+        if !bvh.triangles.is_empty() {
+            bvh.build(0, bvh.triangles.len());
+        }
+        bvh
+    }
+
+    // Add the node of the triangles order[start..end], split at the median of
+    // the longest axis of their centers
+    fn build(&mut self, start: usize, end: usize) -> usize {
+        let triangles = &self.triangles;
+        let bounds = Aabb::from_points(
+            self.order[start..end]
+                .iter()
+                .flat_map(|&t| triangles[t].iter().copied()),
+        );
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            bounds,
+            start,
+            count: end - start,
+        });
+        if end - start <= LEAF_SIZE {
+            return index;
+        }
+
+        let center = |t: usize| {
+            let [a, b, c] = triangles[t];
+            (a.to_vec() + b.to_vec() + c.to_vec()) / 3.0
+        };
+        let centers = Aabb::from_points(
+            self.order[start..end]
+                .iter()
+                .map(|&t| Point3::from_vec(center(t))),
+        );
+        let extent = centers.max - centers.min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+        let middle = (start + end) / 2;
+        self.order[start..end].select_nth_unstable_by(middle - start, |&a, &b| {
+            center(a)[axis].total_cmp(&center(b)[axis])
+        });
+
+        self.build(start, middle);
+        let second = self.build(middle, end);
+        self.nodes[index].start = second;
+        self.nodes[index].count = 0;
+        index
+    }
+
+    // Closest hit of the ray before max_distance
+    pub fn intersect(&self, ray: &Ray, max_distance: f32) -> Option<Hit> {
+        let mut closest: Option<Hit> = None;
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = match self.nodes.get(index) {
+                Some(node) => node,
+                None => break,
+            };
+            let limit = closest.map_or(max_distance, |hit| hit.distance);
+            if node.bounds.ray_distance(ray, limit).is_none() {
+                continue;
+            }
+            if node.count == 0 {
+                stack.push(node.start);
+                stack.push(index + 1);
+                continue;
+            }
+            for &triangle in &self.order[node.start..node.start + node.count] {
+                let limit = closest.map_or(max_distance, |hit| hit.distance);
+                if let Some((distance, barycentric)) = hit_triangle(ray, &self.triangles[triangle])
+                {
+                    if distance < limit {
+                        closest = Some(Hit {
+                            distance,
+                            triangle,
+                            barycentric,
+                        });
+                    }
+                }
+            }
+        }
+        closest
+    }
+}
+
+// Möller-Trumbore intersection, hits behind the origin are ignored
+fn hit_triangle(ray: &Ray, triangle: &[Point3<f32>; 3]) -> Option<(f32, Vector2<f32>)> {
+    let edge1 = triangle[1] - triangle[0];
+    let edge2 = triangle[2] - triangle[0];
+    let p = ray.direction.cross(edge2);
+    let determinant = edge1.dot(p);
+    if determinant.abs() < f32::EPSILON {
+        return None;
+    }
+    let inverse = 1.0 / determinant;
+    let s = ray.origin - triangle[0];
+    let u = s.dot(p) * inverse;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(edge1);
+    let v = ray.direction.dot(q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let distance = edge2.dot(q) * inverse;
+    (distance > 0.0).then(|| (distance, Vector2::new(u, v)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // xorshift numbers in [0, 1), the same on every run
+    fn random(state: &mut u32) -> f32 {
+        *state ^= *state << 13;
+        *state ^= *state >> 17;
+        *state ^= *state << 5;
+        (*state >> 8) as f32 / (1 << 24) as f32
+    }
+
+    fn point(state: &mut u32) -> Point3<f32> {
+        Point3::new(random(state), random(state), random(state)) * 4.0 - Vector3::new(2.0, 2.0, 2.0)
+    }
+
+    #[test]
+    fn hits_match_brute_force() {
+        let mut state = 0x9e37_79b9;
+        // small triangles scattered in a box, crossing each other
+        let mut mesh = tobj::Mesh::default();
+        for i in 0..200 {
+            let corner = point(&mut state);
+            for _ in 0..3 {
+                let offset = point(&mut state) * 0.2;
+                mesh.positions.extend_from_slice(&[
+                    corner.x + offset.x,
+                    corner.y + offset.y,
+                    corner.z + offset.z,
+                ]);
+            }
+            mesh.indices
+                .extend_from_slice(&[i * 3, i * 3 + 1, i * 3 + 2]);
+        }
+        let bvh = TriangleBvh::new(&mesh);
+
+        let mut hits = 0;
+        for _ in 0..500 {
+            let origin = point(&mut state) * 1.5;
+            let target = point(&mut state);
+            let ray = Ray {
+                origin,
+                direction: (target - origin).normalize(),
+            };
+            let max_distance = random(&mut state) * 6.0;
+            let expected = bvh
+                .triangles
+                .iter()
+                .enumerate()
+                .filter_map(|(triangle, vertices)| {
+                    hit_triangle(&ray, vertices).map(|(distance, _)| (distance, triangle))
+                })
+                .filter(|(distance, _)| *distance < max_distance)
+                .min_by(|a, b| a.0.total_cmp(&b.0));
+            let hit = bvh.intersect(&ray, max_distance);
+            assert_eq!(
+                hit.map(|hit| (hit.distance, hit.triangle)),
+                expected,
+                "{:?}",
+                ray
+            );
+            hits += usize::from(hit.is_some());
+        }
+        // the rays don't all miss
+        assert!(hits > 50, "{} hits", hits);
+    }
+
+    #[test]
+    fn empty_mesh() {
+        let bvh = TriangleBvh::new(&tobj::Mesh::default());
+        let ray = Ray {
+            origin: Point3::new(0.0, 0.0, 0.0),
+            direction: Vector3::unit_z(),
+        };
+        assert!(bvh.intersect(&ray, f32::MAX).is_none());
+    }
+}
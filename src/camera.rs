@@ -1,3 +1,4 @@
+use crate::{bounds, renderer};
 use cgmath::*;
 use std::f32::consts::FRAC_PI_2;
 use std::time::Duration;
@@ -15,6 +16,18 @@ pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
 
 const SAFE_FRAC_PI_2: f32 = FRAC_PI_2 - 0.0001;
 
+// Space left around a framed model, as a fraction of its bounding radius
+pub const FRAMING_PADDING: f32 = 0.1;
+
+// Largest far to near ratio of a fitted depth range, for cameras inside the
+// scene bounds
+const MAX_DEPTH_RATIO: f32 = 10_000.0;
+
+// change of the orbit distance per scrolled pixel, a line scrolls by 10%
+const ORBIT_ZOOM: f32 = 0.001;
+// closest an orbiting camera gets to its target
+const MIN_ORBIT_DISTANCE: f32 = 0.01;
+
 #[derive(Debug)]
 pub struct CameraParameters {
     pub position: Point3<f32>,
@@ -35,12 +48,12 @@ impl CameraParameters {
         }
     }
 
+    pub fn direction(&self) -> Vector3<f32> {
+        Vector3::new(self.yaw.0.cos(), self.pitch.0.sin(), self.yaw.0.sin()).normalize()
+    }
+
     pub fn calc_matrix(&self) -> Matrix4<f32> {
-        Matrix4::look_to_rh(
-            self.position,
-            Vector3::new(self.yaw.0.cos(), self.pitch.0.sin(), self.yaw.0.sin()).normalize(),
-            Vector3::unit_y(),
-        )
+        Matrix4::look_to_rh(self.position, self.direction(), Vector3::unit_y())
     }
 }
 
@@ -65,9 +78,50 @@ impl Projection {
         self.aspect = width as f32 / height as f32;
     }
 
+    // Near and far planes of a camera as close as they go around the boxes,
+    // see Camera::fit_depth_range
+    fn fit_depth_range<I: IntoIterator<Item = bounds::Aabb>>(
+        &mut self,
+        camera: &CameraParameters,
+        bounds: I,
+    ) {
+        let eye = camera.position;
+        let direction = camera.direction();
+        let (mut nearest, mut farthest) = (f32::MAX, f32::MIN);
+        for aabb in bounds.into_iter().filter(|aabb| !aabb.is_empty()) {
+            // depth of the center, and how far the corners go along the view
+            let center = (aabb.center() - eye).dot(direction);
+            let half = (aabb.max - aabb.min) * 0.5;
+            let extent = (half.x * direction.x).abs()
+                + (half.y * direction.y).abs()
+                + (half.z * direction.z).abs();
+            nearest = nearest.min(center - extent);
+            farthest = farthest.max(center + extent);
+        }
+        if farthest <= 0.0 {
+            return;
+        }
+        // a little room, so that the fitted faces aren't clipped
+        self.zfar = farthest * 1.01;
+        self.znear = (nearest * 0.99).max(self.zfar / MAX_DEPTH_RATIO);
+    }
+
     pub fn calc_matrix(&self) -> Matrix4<f32> {
         OPENGL_TO_WGPU_MATRIX * perspective(self.fovy, self.aspect, self.znear, self.zfar)
     }
+
+    // Projection of one eye drawn side by side with the other, on half the width
+    pub fn calc_eye_matrix(&self) -> Matrix4<f32> {
+        OPENGL_TO_WGPU_MATRIX * perspective(self.fovy, self.aspect * 0.5, self.znear, self.zfar)
+    }
+
+    // Distance at which a sphere of the given radius fits in the view frustum
+    pub fn framing_distance(&self, radius: f32) -> f32 {
+        let half_fovy = self.fovy.0 * 0.5;
+        // the narrowest of the vertical and horizontal fov limits the fit
+        let half_fovx = (half_fovy.tan() * self.aspect).atan();
+        radius / half_fovy.min(half_fovx).sin()
+    }
 }
 
 #[derive(Debug)]
@@ -83,6 +137,11 @@ pub struct CameraController {
     scroll: f32,
     speed: f32,
     sensitivity: f32,
+    // only look around, e.g. from the center of a panorama
+    pub rotation_only: bool,
+    // turn around this point instead of looking around, scrolling gets closer
+    // or further, e.g. to inspect a model
+    pub orbit_target: Option<Point3<f32>>,
 }
 
 impl CameraController {
@@ -99,6 +158,8 @@ impl CameraController {
             scroll: 0.0,
             speed,
             sensitivity,
+            rotation_only: false,
+            orbit_target: None,
         }
     }
 
@@ -150,9 +211,7 @@ impl CameraController {
         };
     }
 
-    pub fn update_camera(&mut self, camera: &mut CameraParameters, dt: Duration) {
-        let dt = dt.as_secs_f32();
-
+    fn translate_camera(&mut self, camera: &mut CameraParameters, dt: f32) {
         // Move forward/backward and left/right
         let (yaw_sin, yaw_cos) = camera.yaw.0.sin_cos();
         let forward = Vector3::new(yaw_cos, 0.0, yaw_sin).normalize();
@@ -173,6 +232,18 @@ impl CameraController {
         // Move up/down. Since we don't use roll, we can just
         // modify the y coordinate directly.
         camera.position.y += (self.amount_up - self.amount_down) * self.speed * dt;
+    }
+
+    pub fn update_camera(&mut self, camera: &mut CameraParameters, dt: Duration) {
+        let dt = dt.as_secs_f32();
+        let orbit_distance = self.orbit_target.map(|target| {
+            (target - camera.position).magnitude() * (self.scroll * ORBIT_ZOOM).exp()
+        });
+        if self.rotation_only || orbit_distance.is_some() {
+            self.scroll = 0.0;
+        } else {
+            self.translate_camera(camera, dt);
+        }
 
         // Rotate
         camera.yaw += Rad(self.rotate_horizontal) * self.sensitivity * dt;
@@ -190,12 +261,17 @@ impl CameraController {
         } else if camera.pitch > Rad(SAFE_FRAC_PI_2) {
             camera.pitch = Rad(SAFE_FRAC_PI_2);
         }
+
+        // orbiting cameras keep looking at their target from the new angle
+        if let (Some(target), Some(distance)) = (self.orbit_target, orbit_distance) {
+            camera.position = target - camera.direction() * distance.max(MIN_ORBIT_DISTANCE);
+        }
     }
 }
 
 // We need this for Rust to store our data correctly for the shaders
 #[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
     view_position: [f32; 4],
     // We can't use cgmath with bytemuck directly so we'll have
@@ -211,12 +287,195 @@ impl CameraUniform {
         }
     }
 
+    // Uniform of a view set outside of a camera, e.g. to render offscreen
+    pub(crate) fn from_view(position: Point3<f32>, view_proj: Matrix4<f32>) -> Self {
+        Self {
+            view_position: position.to_homogeneous().into(),
+            view_proj: view_proj.into(),
+        }
+    }
+
     pub fn update_view_proj(&mut self, camera: &CameraParameters, projection: &Projection) {
         self.view_position = camera.position.to_homogeneous().into();
         self.view_proj = (projection.calc_matrix() * camera.calc_matrix()).into();
     }
 }
 
+// Area of the surface a camera draws to, in pixels from the top left corner
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Viewport {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    // Viewport covering a whole window or texture
+    pub fn from_size(size: winit::dpi::PhysicalSize<u32>) -> Self {
+        Self::new(0.0, 0.0, size.width as f32, size.height as f32)
+    }
+
+    // Normalized device coordinates of a pixel, y going up from -1 to 1
+    pub fn screen_to_ndc(&self, px: f32, py: f32) -> Point2<f32> {
+        Point2::new(
+            (px - self.x) / self.width * 2.0 - 1.0,
+            1.0 - (py - self.y) / self.height * 2.0,
+        )
+    }
+
+    pub fn ndc_to_screen(&self, ndc: Point2<f32>) -> Point2<f32> {
+        Point2::new(
+            self.x + (ndc.x + 1.0) * 0.5 * self.width,
+            self.y + (1.0 - ndc.y) * 0.5 * self.height,
+        )
+    }
+}
+
+// Ray through a point in normalized device coordinates, from the near plane
+fn unproject(view_proj: &Matrix4<f32>, ndc: Point2<f32>) -> Ray {
+    let inverse = view_proj.invert().unwrap_or_else(Matrix4::identity);
+    // wgpu depths go from 0 on the near plane to 1 on the far one
+    let near = Point3::from_homogeneous(inverse * Vector4::new(ndc.x, ndc.y, 0.0, 1.0));
+    let far = Point3::from_homogeneous(inverse * Vector4::new(ndc.x, ndc.y, 1.0, 1.0));
+    Ray {
+        origin: near,
+        direction: (far - near).normalize(),
+    }
+}
+
+// Normalized device coordinates of a point, None behind the camera
+fn project(view_proj: &Matrix4<f32>, point: Point3<f32>) -> Option<Point3<f32>> {
+    let clip = view_proj * point.to_homogeneous();
+    if clip.w <= 0.0 {
+        return None;
+    }
+    Some(Point3::from_homogeneous(clip))
+}
+
+// Half line from the camera into the scene, the direction is normalized
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    pub fn at(&self, distance: f32) -> Point3<f32> {
+        self.origin + self.direction * distance
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+// Uniforms of the two eyes of a stereo camera, bound in place of the camera
+// uniform when drawing each half of the view
+pub struct StereoEyes {
+    // distance between the eyes, in scene units
+    pub separation: f32,
+    uniforms: [CameraUniform; 2],
+    buffers: [wgpu::Buffer; 2],
+    bind_groups: [wgpu::BindGroup; 2],
+}
+
+impl StereoEyes {
+    fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, separation: f32) -> Self {
+        let uniforms = [CameraUniform::new(); 2];
+        let buffers = [Eye::Left, Eye::Right].map(|eye| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(match eye {
+                    Eye::Left => "Left Eye Buffer",
+                    Eye::Right => "Right Eye Buffer",
+                }),
+                contents: bytemuck::cast_slice(&[uniforms[eye as usize]]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            })
+        });
+        let bind_groups = [&buffers[0], &buffers[1]].map(|buffer| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                }],
+                label: Some("eye_bind_group"),
+            })
+        });
+
+        Self {
+            separation,
+            uniforms,
+            buffers,
+            bind_groups,
+        }
+    }
+
+    pub fn bind_group(&self, eye: Eye) -> &wgpu::BindGroup {
+        &self.bind_groups[eye as usize]
+    }
+
+    fn update(&mut self, queue: &wgpu::Queue, camera: &CameraParameters, projection: &Projection) {
+        let right = camera.direction().cross(Vector3::unit_y()).normalize();
+        for eye in [Eye::Left, Eye::Right] {
+            // parallel eyes, each one shifted by half the separation
+            let offset = match eye {
+                Eye::Left => -0.5,
+                Eye::Right => 0.5,
+            } * self.separation;
+            let position = camera.position + right * offset;
+            let view = Matrix4::look_to_rh(position, camera.direction(), Vector3::unit_y());
+            let uniform = CameraUniform {
+                view_position: position.to_homogeneous().into(),
+                view_proj: (projection.calc_eye_matrix() * view).into(),
+            };
+            // eyes that didn't move keep their buffers
+            if uniform != self.uniforms[eye as usize] {
+                self.uniforms[eye as usize] = uniform;
+                queue.write_buffer(
+                    &self.buffers[eye as usize],
+                    0,
+                    bytemuck::cast_slice(&[uniform]),
+                );
+            }
+        }
+    }
+}
+
+// Camera move started by a framing request, played over several frames
+struct Transition {
+    from: Point3<f32>,
+    to: Point3<f32>,
+    elapsed: Duration,
+    duration: Duration,
+}
+
+impl Transition {
+    // Returns the camera position for the current time and whether the transition is over
+    fn advance(&mut self, dt: Duration) -> (Point3<f32>, bool) {
+        self.elapsed += dt;
+        let t = (self.elapsed.as_secs_f32() / self.duration.as_secs_f32()).min(1.0);
+        // smoothstep, to ease in and out of the move
+        let t = t * t * (3.0 - 2.0 * t);
+        (
+            self.from + (self.to - self.from) * t,
+            self.elapsed >= self.duration,
+        )
+    }
+}
+
 pub struct Camera {
     camera_parameters: CameraParameters,
     pub projection: Projection,
@@ -225,6 +484,13 @@ pub struct Camera {
     camera_buffer: wgpu::Buffer,
     pub bind_group_layout: wgpu::BindGroupLayout,
     pub bind_group: wgpu::BindGroup,
+    // duration of the move when framing, None to jump directly to the new position
+    pub framing_transition: Option<Duration>,
+    transition: Option<Transition>,
+    // set to draw the view side by side for both eyes
+    pub stereo: Option<StereoEyes>,
+    // fit the near and far planes to the scene every frame
+    pub depth_fit: bool,
 }
 
 impl Camera {
@@ -243,19 +509,7 @@ impl Camera {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            }],
-            label: Some("camera_bind_group_layout"),
-        });
+        let bind_group_layout = Self::create_layout(device);
 
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &bind_group_layout,
@@ -274,18 +528,262 @@ impl Camera {
             camera_buffer,
             bind_group_layout,
             bind_group,
+            framing_transition: Some(Duration::from_millis(300)),
+            transition: None,
+            stereo: None,
+            depth_fit: false,
+        }
+    }
+
+    pub fn create_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("camera_bind_group_layout"),
+        })
+    }
+
+    pub fn enable_stereo(&mut self, device: &wgpu::Device, separation: f32) {
+        self.stereo = Some(StereoEyes::new(device, &self.bind_group_layout, separation));
+    }
+
+    // Move the camera along its view direction so that the box fills the view,
+    // orbiting cameras turn around its center from then on
+    pub fn frame_aabb(&mut self, aabb: &bounds::Aabb, padding: f32) {
+        if aabb.is_empty() || self.camera_controller.rotation_only {
+            return;
+        }
+        if self.camera_controller.orbit_target.is_some() {
+            self.camera_controller.orbit_target = Some(aabb.center());
         }
+
+        let radius = aabb.radius() * (1.0 + padding);
+        let distance = self.projection.framing_distance(radius);
+        // keep the far side of the box in the depth range
+        self.projection.zfar = self.projection.zfar.max(distance + radius);
+
+        let target = aabb.center() - self.camera_parameters.direction() * distance;
+        self.transition = None;
+        match self.framing_transition {
+            Some(duration) if !duration.is_zero() => {
+                self.transition = Some(Transition {
+                    from: self.camera_parameters.position,
+                    to: target,
+                    elapsed: Duration::ZERO,
+                    duration,
+                });
+            }
+            _ => self.camera_parameters.position = target,
+        }
+    }
+
+    pub fn position(&self) -> Point3<f32> {
+        self.camera_parameters.position
+    }
+
+    // Turn the camera towards the target and orbit around it, see
+    // CameraController::orbit_target
+    pub fn orbit(&mut self, target: Point3<f32>) {
+        let offset = target - self.camera_parameters.position;
+        let horizontal = (offset.x * offset.x + offset.z * offset.z).sqrt();
+        if horizontal > 0.0 {
+            self.camera_parameters.yaw = Rad(offset.z.atan2(offset.x));
+            // direction() normalizes (cos yaw, sin pitch, sin yaw), which
+            // looks 45 degrees up or down at most
+            let slope = (offset.y / horizontal).clamp(-1.0, 1.0);
+            self.camera_parameters.pitch = Rad(slope.asin().clamp(-SAFE_FRAC_PI_2, SAFE_FRAC_PI_2));
+        }
+        self.camera_controller.orbit_target = Some(target);
+    }
+
+    pub fn view_proj(&self) -> Matrix4<f32> {
+        self.projection.calc_matrix() * self.camera_parameters.calc_matrix()
+    }
+
+    pub fn frustum(&self) -> bounds::Frustum {
+        bounds::Frustum::from_view_proj(&self.view_proj())
+    }
+
+    // Ray through a pixel of the viewport, starting on the near plane
+    pub fn screen_to_ray(&self, px: f32, py: f32, viewport: &Viewport) -> Ray {
+        unproject(&self.view_proj(), viewport.screen_to_ndc(px, py))
+    }
+
+    // Normalized device coordinates of a point, None when it is behind the camera
+    pub fn world_to_ndc(&self, point: Point3<f32>) -> Option<Point3<f32>> {
+        project(&self.view_proj(), point)
+    }
+
+    // Pixel a point is drawn at, e.g. to place a label over it
+    pub fn world_to_screen(&self, point: Point3<f32>, viewport: &Viewport) -> Option<Point2<f32>> {
+        self.world_to_ndc(point)
+            .map(|ndc| viewport.ndc_to_screen(Point2::new(ndc.x, ndc.y)))
+    }
+
+    pub fn frame_model(&mut self, model: &renderer::Model) {
+        self.frame_aabb(&model.bounds, FRAMING_PADDING);
+    }
+
+    // Move the near and far planes as close as they go around the boxes, for
+    // the most depth precision. Nothing changes when no box is in front.
+    pub fn fit_depth_range<I: IntoIterator<Item = bounds::Aabb>>(&mut self, bounds: I) {
+        self.projection
+            .fit_depth_range(&self.camera_parameters, bounds);
     }
 
     pub fn update(&mut self, queue: &wgpu::Queue, dt: std::time::Duration) {
+        self.advance(dt);
+        self.upload(queue);
+    }
+
+    // Move the camera by its controller and framing transition
+    pub fn advance(&mut self, dt: std::time::Duration) {
         self.camera_controller
             .update_camera(&mut self.camera_parameters, dt);
-        self.camera_uniform
-            .update_view_proj(&self.camera_parameters, &self.projection);
-        queue.write_buffer(
-            &self.camera_buffer,
-            0,
-            bytemuck::cast_slice(&[self.camera_uniform]),
+        if let Some(transition) = self.transition.as_mut() {
+            let (position, done) = transition.advance(dt);
+            self.camera_parameters.position = position;
+            if done {
+                self.transition = None;
+            }
+        }
+    }
+
+    pub fn upload(&mut self, queue: &wgpu::Queue) {
+        // the buffer is only written when the camera moved or its projection
+        // changed, static views upload nothing
+        let mut camera_uniform = self.camera_uniform;
+        camera_uniform.update_view_proj(&self.camera_parameters, &self.projection);
+        if camera_uniform != self.camera_uniform {
+            self.camera_uniform = camera_uniform;
+            queue.write_buffer(
+                &self.camera_buffer,
+                0,
+                bytemuck::cast_slice(&[self.camera_uniform]),
+            );
+        }
+        if let Some(stereo) = self.stereo.as_mut() {
+            stereo.update(queue, &self.camera_parameters, &self.projection);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn looking_forward() -> (CameraParameters, Projection) {
+        // looks down -z from the origin
+        let camera = CameraParameters::new((0.0, 0.0, 0.0), Deg(-90.0), Deg(0.0));
+        let projection = Projection::new(800, 600, Deg(45.0), 0.1, 100.0);
+        (camera, projection)
+    }
+
+    fn view_proj(camera: &CameraParameters, projection: &Projection) -> Matrix4<f32> {
+        projection.calc_matrix() * camera.calc_matrix()
+    }
+
+    // distance from the point to the line of the ray
+    fn miss(ray: &Ray, point: Point3<f32>) -> f32 {
+        let offset = point - ray.origin;
+        (offset - ray.direction * offset.dot(ray.direction)).magnitude()
+    }
+
+    #[test]
+    fn screen_and_world_round_trip() {
+        let (camera, projection) = looking_forward();
+        let view_proj = view_proj(&camera, &projection);
+        let viewport = Viewport::new(100.0, 50.0, 800.0, 600.0);
+        for point in [
+            Point3::new(0.0, 0.0, -5.0),
+            Point3::new(1.5, -0.5, -3.0),
+            Point3::new(-4.0, 2.0, -20.0),
+        ] {
+            let ndc = project(&view_proj, point).unwrap();
+            let pixel = viewport.ndc_to_screen(Point2::new(ndc.x, ndc.y));
+            let ray = unproject(&view_proj, viewport.screen_to_ndc(pixel.x, pixel.y));
+            assert!(miss(&ray, point) < 1e-3, "{:?} missed by {:?}", point, ray);
+            assert!(ray.direction.dot(point - ray.origin) > 0.0);
+        }
+        // behind the camera
+        assert!(project(&view_proj, Point3::new(0.0, 0.0, 5.0)).is_none());
+    }
+
+    #[test]
+    fn viewport_center_looks_forward() {
+        let (camera, projection) = looking_forward();
+        let viewport = Viewport::new(100.0, 50.0, 800.0, 600.0);
+        let ndc = viewport.screen_to_ndc(500.0, 350.0);
+        assert!(ndc.x.abs() < 1e-6 && ndc.y.abs() < 1e-6);
+        let ray = unproject(&view_proj(&camera, &projection), ndc);
+        assert!((ray.direction - camera.direction()).magnitude() < 1e-4);
+        // starting on the near plane
+        assert!((ray.origin - Point3::new(0.0, 0.0, -0.1)).magnitude() < 1e-4);
+    }
+
+    #[test]
+    fn screen_y_goes_down() {
+        let (camera, projection) = looking_forward();
+        let view_proj = view_proj(&camera, &projection);
+        let viewport = Viewport::new(0.0, 0.0, 800.0, 600.0);
+        assert_eq!(viewport.screen_to_ndc(0.0, 0.0), Point2::new(-1.0, 1.0));
+        assert_eq!(
+            viewport.ndc_to_screen(Point2::new(1.0, -1.0)),
+            Point2::new(800.0, 600.0)
         );
+        // a point above the view is drawn in its top half, and the top row
+        // casts rays going up
+        let ndc = project(&view_proj, Point3::new(0.0, 1.0, -5.0)).unwrap();
+        assert!(viewport.ndc_to_screen(Point2::new(ndc.x, ndc.y)).y < 300.0);
+        let ray = unproject(&view_proj, viewport.screen_to_ndc(400.0, 0.0));
+        assert!(ray.direction.y > 0.0);
+    }
+
+    fn aabb(min: (f32, f32, f32), max: (f32, f32, f32)) -> bounds::Aabb {
+        bounds::Aabb {
+            min: min.into(),
+            max: max.into(),
+        }
+    }
+
+    #[test]
+    fn depth_range_around_boxes_in_front() {
+        let (camera, mut projection) = looking_forward();
+        projection.fit_depth_range(
+            &camera,
+            [
+                aabb((-1.0, -1.0, -6.0), (1.0, 1.0, -4.0)),
+                aabb((2.0, 0.0, -30.0), (3.0, 1.0, -20.0)),
+                bounds::Aabb::empty(),
+            ],
+        );
+        assert!((projection.znear - 4.0 * 0.99).abs() < 1e-4);
+        assert!((projection.zfar - 30.0 * 1.01).abs() < 1e-4);
+    }
+
+    #[test]
+    fn depth_range_kept_for_boxes_behind() {
+        let (camera, mut projection) = looking_forward();
+        projection.fit_depth_range(&camera, [aabb((-1.0, -1.0, 4.0), (1.0, 1.0, 6.0))]);
+        assert_eq!((projection.znear, projection.zfar), (0.1, 100.0));
+        assert!(projection.znear > 0.0 && projection.znear < projection.zfar);
+    }
+
+    #[test]
+    fn depth_range_from_inside_a_box() {
+        let (camera, mut projection) = looking_forward();
+        projection.fit_depth_range(&camera, [aabb((-10.0, -10.0, -10.0), (10.0, 10.0, 5.0))]);
+        assert!((projection.zfar - 10.0 * 1.01).abs() < 1e-4);
+        // the near plane can't go behind the eye, it stops at the minimum
+        assert_eq!(projection.znear, projection.zfar / MAX_DEPTH_RATIO);
+        assert!(projection.znear > 0.0);
     }
 }
@@ -0,0 +1,169 @@
+use crate::texture;
+use std::sync::Arc;
+use winit::window::Window;
+
+// Optional features enabled whenever the adapter has them, lines are drawn by
+// the wireframe view
+const OPTIONAL_FEATURES: wgpu::Features = wgpu::Features::TEXTURE_COMPRESSION_BC
+    .union(wgpu::Features::TEXTURE_COMPRESSION_ETC2)
+    .union(wgpu::Features::TEXTURE_COMPRESSION_ASTC_LDR)
+    .union(wgpu::Features::POLYGON_MODE_LINE);
+
+// Formats whose support is listed in the capabilities
+const REPORTED_FORMATS: [wgpu::TextureFormat; 22] = [
+    wgpu::TextureFormat::R8Unorm,
+    wgpu::TextureFormat::Rg8Unorm,
+    wgpu::TextureFormat::Rgba8Unorm,
+    wgpu::TextureFormat::Rgba8UnormSrgb,
+    wgpu::TextureFormat::Bgra8Unorm,
+    wgpu::TextureFormat::Bgra8UnormSrgb,
+    wgpu::TextureFormat::Rgb10a2Unorm,
+    wgpu::TextureFormat::Rg11b10Float,
+    wgpu::TextureFormat::Rgba16Float,
+    wgpu::TextureFormat::Rgba32Float,
+    wgpu::TextureFormat::Depth32Float,
+    wgpu::TextureFormat::Depth24Plus,
+    wgpu::TextureFormat::Depth24PlusStencil8,
+    wgpu::TextureFormat::Bc1RgbaUnormSrgb,
+    wgpu::TextureFormat::Bc3RgbaUnormSrgb,
+    wgpu::TextureFormat::Bc5RgUnorm,
+    wgpu::TextureFormat::Bc6hRgbUfloat,
+    wgpu::TextureFormat::Bc7RgbaUnormSrgb,
+    wgpu::TextureFormat::Etc2RgbUnormSrgb,
+    wgpu::TextureFormat::Etc2RgbA1UnormSrgb,
+    wgpu::TextureFormat::Astc4x4RgbaUnormSrgb,
+    wgpu::TextureFormat::Astc8x8RgbaUnormSrgb,
+];
+
+// What the adapter and the device created on it can do
+#[derive(Debug, Clone)]
+pub struct Capabilities {
+    pub adapter: wgpu::AdapterInfo,
+    // features enabled on the device, usable right away
+    pub features: wgpu::Features,
+    // features the adapter could enable
+    pub adapter_features: wgpu::Features,
+    pub limits: wgpu::Limits,
+    // support of the common formats, as reported by the adapter
+    pub formats: Vec<(wgpu::TextureFormat, wgpu::TextureFormatFeatures)>,
+    pub preferred_surface_format: wgpu::TextureFormat,
+}
+
+impl Capabilities {
+    pub fn supports_bc(&self) -> bool {
+        self.features
+            .contains(wgpu::Features::TEXTURE_COMPRESSION_BC)
+    }
+
+    pub fn supports_etc2(&self) -> bool {
+        self.features
+            .contains(wgpu::Features::TEXTURE_COMPRESSION_ETC2)
+    }
+
+    pub fn supports_astc(&self) -> bool {
+        self.features
+            .contains(wgpu::Features::TEXTURE_COMPRESSION_ASTC_LDR)
+    }
+
+    // Largest width or height of a 2d texture
+    pub fn max_texture_size(&self) -> u32 {
+        self.limits.max_texture_dimension_2d
+    }
+
+    // Whether textures of the format can be created with all the usages. Formats
+    // missing from the report are considered unsupported.
+    pub fn supports_format(
+        &self,
+        format: wgpu::TextureFormat,
+        usages: wgpu::TextureUsages,
+    ) -> bool {
+        self.formats.iter().any(|(reported, features)| {
+            *reported == format && features.allowed_usages.contains(usages)
+        })
+    }
+
+    // Whether depth targets can be created with the format, see
+    // Texture::DEPTH_FORMATS
+    pub fn supports_depth_format(&self, format: wgpu::TextureFormat) -> bool {
+        texture::Texture::DEPTH_FORMATS.contains(&format)
+            && self.supports_format(
+                format,
+                wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            )
+    }
+}
+
+// GPU handles shared by all the windows of a scene
+pub struct GpuContext {
+    pub instance: wgpu::Instance,
+    pub adapter: wgpu::Adapter,
+    // shared with the worker threads building pipelines
+    pub device: Arc<wgpu::Device>,
+    pub queue: wgpu::Queue,
+    // format preferred by the surface of the first window
+    pub surface_format: wgpu::TextureFormat,
+}
+
+impl GpuContext {
+    // The adapter must be able to present to the window, so the surface of the
+    // first window is created along with the context
+    pub async fn new(window: &Window) -> (Self, wgpu::Surface) {
+        // The instance is a handle to our GPU
+        // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let surface = unsafe { instance.create_surface(window) };
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: Some(&surface),
+                force_fallback_adapter: false,
+            })
+            .await
+            .unwrap();
+        let surface_format = surface.get_preferred_format(&adapter).unwrap();
+
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    features: adapter.features() & OPTIONAL_FEATURES,
+                    limits: wgpu::Limits::default(),
+                    label: None,
+                },
+                None, // Trace path
+            )
+            .await
+            .unwrap();
+
+        (
+            Self {
+                instance,
+                adapter,
+                device: Arc::new(device),
+                queue,
+                surface_format,
+            },
+            surface,
+        )
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        let formats = REPORTED_FORMATS
+            .iter()
+            .map(|format| (*format, self.adapter.get_texture_format_features(*format)))
+            .collect();
+
+        Capabilities {
+            adapter: self.adapter.get_info(),
+            features: self.device.features(),
+            adapter_features: self.adapter.features(),
+            limits: self.device.limits(),
+            formats,
+            preferred_surface_format: self.surface_format,
+        }
+    }
+
+    // The window must outlive the surface
+    pub fn create_surface(&self, window: &Window) -> wgpu::Surface {
+        unsafe { self.instance.create_surface(window) }
+    }
+}
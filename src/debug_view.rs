@@ -0,0 +1,296 @@
+use crate::globals::DITHER_IMPORT;
+use crate::material_graph::{VERTEX, VERTEX_INSTANCED, VERTEX_IO};
+use crate::renderer::{InstanceRaw, ModelRenderer, ModelVertex, PipelineInputs, Vertex};
+use crate::{binding, globals};
+
+// What the scene shows, the V key goes through them at runtime
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum DebugView {
+    // the shaders of the models
+    #[default]
+    Shaded,
+    // distance from the camera, through the debug palette
+    Depth,
+    // world normals as rgb
+    Normals,
+    // edges of the triangles, on adapters able to draw lines
+    Wireframe,
+    // fragments drawn per pixel, hidden or not, through the debug palette
+    Overdraw,
+}
+
+impl DebugView {
+    pub fn next(self) -> Self {
+        match self {
+            DebugView::Shaded => DebugView::Depth,
+            DebugView::Depth => DebugView::Normals,
+            DebugView::Normals => DebugView::Wireframe,
+            DebugView::Wireframe => DebugView::Overdraw,
+            DebugView::Overdraw => DebugView::Shaded,
+        }
+    }
+}
+
+// Color map of the depth and overdraw views. They are all perceptually uniform and
+// readable with the common color vision deficiencies.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum DebugPalette {
+    #[default]
+    Viridis,
+    Inferno,
+    Grayscale,
+}
+
+// Format of the overdraw counts, blended so that each fragment adds one
+const OVERDRAW_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R16Float;
+
+fn palette_source(palette: DebugPalette) -> String {
+    let palette = match palette {
+        DebugPalette::Viridis => 0,
+        DebugPalette::Inferno => 1,
+        DebugPalette::Grayscale => 2,
+    };
+    include_str!("shader/palette.wgsl").replace("DEBUG_PALETTE", &palette.to_string())
+}
+
+// Pipeline drawing a model for the debug view, None for the shaded view. The
+// overdraw view draws the models into the overdraw target of the view, the
+// other ones into the scene targets. The depth scale is the distance shown at
+// the middle of the palette.
+pub(crate) fn debug_pipeline(
+    inputs: &PipelineInputs,
+    renderer: &ModelRenderer,
+    view: DebugView,
+    palette: DebugPalette,
+    depth_scale: f32,
+) -> Option<wgpu::RenderPipeline> {
+    let mode = match view {
+        DebugView::Shaded => return None,
+        DebugView::Depth => 1,
+        DebugView::Normals => 2,
+        DebugView::Overdraw => 3,
+        DebugView::Wireframe => 4,
+    };
+    let instanced = renderer.instance_buffer.is_some();
+
+    let source = format!(
+        "[[block]]
+struct Camera {{
+    view_position: vec4<f32>;
+    view_proj: mat4x4<f32>;
+}};
+[[group({}), binding(0)]]
+var<uniform> camera: Camera;
+
+{}
+{}{}
+{}
+{}",
+        binding::CAMERA_GROUP,
+        DITHER_IMPORT,
+        VERTEX_IO,
+        if instanced { VERTEX_INSTANCED } else { VERTEX },
+        palette_source(palette),
+        include_str!("shader/debug.wgsl")
+            .replace("DEBUG_MODE", &mode.to_string())
+            .replace("DEBUG_DEPTH_SCALE", &format!("{:?}", depth_scale.max(1e-3)))
+    );
+    let shader = wgpu::ShaderModuleDescriptor {
+        label: Some("Debug Shader"),
+        source: wgpu::ShaderSource::Wgsl(globals::preprocess(&source).into()),
+    };
+
+    // same groups as the model, its material is bound even if unused
+    let layout = inputs.layout(
+        "Debug Pipeline Layout",
+        renderer.model.material_layout.as_ref(),
+    );
+    let device = inputs.device;
+    let (color_format, depth_format) = (inputs.color_format, inputs.depth_format);
+    let mut vertex_layouts = vec![ModelVertex::desc()];
+    if instanced {
+        vertex_layouts.push(InstanceRaw::desc());
+    }
+    if view != DebugView::Overdraw {
+        let polygon_mode = match view {
+            DebugView::Wireframe => wgpu::PolygonMode::Line,
+            _ => wgpu::PolygonMode::Fill,
+        };
+        return Some(ModelRenderer::create_render_pipeline_with_mode(
+            device,
+            &layout,
+            color_format,
+            Some(depth_format),
+            &vertex_layouts,
+            shader,
+            polygon_mode,
+        ));
+    }
+
+    // every fragment is counted, hidden or not
+    let shader = device.create_shader_module(&shader);
+    let additive = wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    };
+    Some(
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Overdraw Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &vertex_layouts,
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: OVERDRAW_FORMAT,
+                    blend: Some(wgpu::BlendState {
+                        color: additive,
+                        alpha: additive,
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+        }),
+    )
+}
+
+// Overdraw counts of a view, at the size of its scene target
+pub(crate) struct OverdrawTarget {
+    pub view: wgpu::TextureView,
+    pub size: (u32, u32),
+    bind_group: wgpu::BindGroup,
+}
+
+// Draws the overdraw counts of a view as a heatmap over its scene target
+pub(crate) struct Heatmap {
+    bind_group_layout: wgpu::BindGroupLayout,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl Heatmap {
+    pub fn new(
+        device: &wgpu::Device,
+        color_format: wgpu::TextureFormat,
+        depth_format: wgpu::TextureFormat,
+        palette: DebugPalette,
+    ) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                },
+                count: None,
+            }],
+            label: Some("heatmap_bind_group_layout"),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Heatmap Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Heatmap Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                format!(
+                    "{}\n{}",
+                    palette_source(palette),
+                    include_str!("shader/overdraw.wgsl")
+                )
+                .into(),
+            ),
+        });
+        // drawn in the scene pass, whose depth target is left as is
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Heatmap Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: color_format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth_format,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+        });
+
+        Self {
+            bind_group_layout,
+            render_pipeline,
+        }
+    }
+
+    pub fn create_target(&self, device: &wgpu::Device, size: (u32, u32)) -> OverdrawTarget {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("overdraw_target"),
+            size: wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: OVERDRAW_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            }],
+            label: Some("heatmap_bind_group"),
+        });
+
+        OverdrawTarget {
+            view,
+            size,
+            bind_group,
+        }
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, target: &'a OverdrawTarget) {
+        render_pass.set_viewport(
+            0.0,
+            0.0,
+            target.size.0 as f32,
+            target.size.1 as f32,
+            0.0,
+            1.0,
+        );
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &target.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,359 @@
+use crate::object::{Object, SanitizeReport};
+use anyhow::{ensure, Result};
+use cgmath::*;
+
+// Control point of the spline a profile is extruded along
+#[derive(Debug, Copy, Clone)]
+pub struct SplinePoint {
+    pub position: Point3<f32>,
+    // roll of the profile around the spline direction, e.g. to bank a road in turns
+    pub bank: Rad<f32>,
+}
+
+// Cross section swept along the spline, in the (right, up) plane of the spline.
+// The surface faces the left side of the profile when walking its points in order.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub points: Vec<Vector2<f32>>,
+    pub closed: bool,
+}
+
+impl Profile {
+    // Flat strip facing up, for roads and rivers
+    pub fn road(width: f32) -> Self {
+        Self {
+            points: vec![
+                Vector2::new(-width * 0.5, 0.0),
+                Vector2::new(width * 0.5, 0.0),
+            ],
+            closed: false,
+        }
+    }
+
+    // Circle facing outward, for pipes
+    pub fn circle(radius: f32, segments: usize) -> Self {
+        Self {
+            points: (0..segments.max(3))
+                .map(|i| {
+                    // walk clockwise so that the left side is outside
+                    let angle = -std::f32::consts::TAU * i as f32 / segments.max(3) as f32;
+                    Vector2::new(angle.cos(), angle.sin()) * radius
+                })
+                .collect(),
+            closed: true,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ExtrudeOptions {
+    // samples taken between two control points
+    pub segments_per_span: usize,
+    // length along the spline covered by one repeat of the texture
+    pub uv_tiling: f32,
+}
+
+impl Default for ExtrudeOptions {
+    fn default() -> Self {
+        Self {
+            segments_per_span: 8,
+            uv_tiling: 1.0,
+        }
+    }
+}
+
+// Catmull-Rom interpolation between p1 and p2
+fn catmull_rom(
+    p0: Point3<f32>,
+    p1: Point3<f32>,
+    p2: Point3<f32>,
+    p3: Point3<f32>,
+    t: f32,
+) -> Point3<f32> {
+    let (t2, t3) = (t * t, t * t * t);
+    let (p0, p1, p2, p3) = (p0.to_vec(), p1.to_vec(), p2.to_vec(), p3.to_vec());
+    Point3::from_vec(
+        (p1 * 2.0
+            + (p2 - p0) * t
+            + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+            + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+            * 0.5,
+    )
+}
+
+// Extrude the profile along a Catmull-Rom spline through the points, into an
+// object without material. The mesh is sanitized like loaded ones, e.g. where
+// the spline folds back on itself.
+pub fn extrude_along_spline(
+    spline: &[SplinePoint],
+    profile: &Profile,
+    options: ExtrudeOptions,
+) -> Result<Object> {
+    ensure!(
+        options.uv_tiling > 0.0,
+        "uv tiling must be positive, got {}",
+        options.uv_tiling
+    );
+    let mut mesh = tobj::Mesh::default();
+    // repeated profile points would give zero length segments, which only
+    // make degenerate triangles
+    let mut points = profile.points.clone();
+    points.dedup_by(|b, a| (*b - *a).magnitude2() < 1e-12);
+    if profile.closed
+        && points.len() > 1
+        && (points[0] - points[points.len() - 1]).magnitude2() < 1e-12
+    {
+        points.pop();
+    }
+    if spline.len() < 2 || points.len() < 2 {
+        return Ok(extrusion(mesh));
+    }
+
+    // sample the spline, the end points are repeated to get a tangent there
+    let segments = options.segments_per_span.max(1);
+    let control = |i: isize| spline[i.clamp(0, spline.len() as isize - 1) as usize];
+    let mut samples = Vec::new();
+    for span in 0..spline.len() - 1 {
+        let i = span as isize;
+        let last_span = span == spline.len() - 2;
+        for step in 0..segments + usize::from(last_span) {
+            let t = step as f32 / segments as f32;
+            let position = catmull_rom(
+                control(i - 1).position,
+                control(i).position,
+                control(i + 1).position,
+                control(i + 2).position,
+                t,
+            );
+            let bank = control(i).bank + (control(i + 1).bank - control(i).bank) * t;
+            samples.push((position, bank));
+        }
+    }
+    // repeated control points give repeated samples, which have no direction
+    samples.dedup_by(|(b, _), (a, _)| (*b - *a).magnitude2() < 1e-12);
+    if samples.len() < 2 {
+        return Ok(extrusion(mesh));
+    }
+
+    // profile vertices, the first one repeated at the end of closed profiles
+    // so that the texture doesn't wrap back on the last segment
+    let mut profile_points = points.clone();
+    if profile.closed {
+        profile_points.push(points[0]);
+    }
+    // normals of the profile segments, facing their left side
+    let count = points.len();
+    let segment_count = if profile.closed { count } else { count - 1 };
+    let segment_normals: Vec<Vector2<f32>> = (0..segment_count)
+        .map(|j| {
+            let direction = points[(j + 1) % count] - points[j];
+            Vector2::new(-direction.y, direction.x).normalize()
+        })
+        .collect();
+    // per point normals of the profile, averaged from the neighbouring segments
+    let profile_normals: Vec<Vector2<f32>> = (0..profile_points.len())
+        .map(|j| {
+            let j = j % count;
+            let previous = (profile.closed || j > 0)
+                .then(|| segment_normals[(j + segment_count - 1) % segment_count]);
+            let next =
+                (profile.closed || j + 1 < count).then(|| segment_normals[j % segment_count]);
+            let sum = previous.unwrap_or_else(Vector2::zero) + next.unwrap_or_else(Vector2::zero);
+            if sum.magnitude2() > 1e-12 {
+                sum.normalize()
+            } else {
+                // the profile folds back on itself here
+                next.or(previous).unwrap()
+            }
+        })
+        .collect();
+    let mut profile_u = vec![0.0];
+    for j in 1..profile_points.len() {
+        profile_u.push(profile_u[j - 1] + (profile_points[j] - profile_points[j - 1]).magnitude());
+    }
+    let profile_length = profile_u[profile_u.len() - 1].max(f32::EPSILON);
+
+    // frames are transported along the spline to avoid twisting, then banked
+    let mut up = Vector3::unit_y();
+    let mut tangent = Vector3::unit_z();
+    let mut distance = 0.0;
+    for (i, (position, bank)) in samples.iter().enumerate() {
+        let before = samples[i.saturating_sub(1)].0;
+        let after = samples[(i + 1).min(samples.len() - 1)].0;
+        // keep the previous direction where the spline turns back on itself
+        if (after - before).magnitude2() > 1e-12 {
+            tangent = (after - before).normalize();
+        }
+        if i > 0 {
+            distance += (position - before).magnitude();
+        }
+
+        let mut transported = up - tangent * up.dot(tangent);
+        if transported.magnitude2() < 1e-8 {
+            // the spline goes straight along the up vector: pick any side
+            transported = tangent.cross(Vector3::unit_x());
+        }
+        up = transported.normalize();
+        let banking = Quaternion::from_axis_angle(tangent, *bank);
+        let frame_up = banking * up;
+        let frame_right = tangent.cross(frame_up);
+
+        for (j, point) in profile_points.iter().enumerate() {
+            let vertex = position + frame_right * point.x + frame_up * point.y;
+            let normal = frame_right * profile_normals[j].x + frame_up * profile_normals[j].y;
+            mesh.positions
+                .extend_from_slice(&[vertex.x, vertex.y, vertex.z]);
+            mesh.normals
+                .extend_from_slice(&[normal.x, normal.y, normal.z]);
+            mesh.texcoords
+                .extend_from_slice(&[profile_u[j] / profile_length, distance / options.uv_tiling]);
+        }
+    }
+
+    // two counter clockwise triangles per quad between consecutive samples
+    let row = profile_points.len() as u32;
+    for i in 0..samples.len() as u32 - 1 {
+        for j in 0..row - 1 {
+            let (a, b) = (i * row + j, i * row + j + 1);
+            let (c, d) = (a + row, b + row);
+            mesh.indices.extend_from_slice(&[a, b, c, b, d, c]);
+        }
+    }
+
+    Ok(extrusion(mesh))
+}
+
+fn extrusion(mesh: tobj::Mesh) -> Object {
+    let mut object = Object {
+        models: vec![tobj::Model::new(mesh, "extrusion".into())],
+        textures: None,
+        sanitize_report: SanitizeReport::default(),
+    };
+    object.sanitize_report = object.sanitize();
+    object
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spline(points: &[[f32; 3]]) -> Vec<SplinePoint> {
+        points
+            .iter()
+            .map(|p| SplinePoint {
+                position: Point3::from(*p),
+                bank: Rad(0.0),
+            })
+            .collect()
+    }
+
+    fn options() -> ExtrudeOptions {
+        ExtrudeOptions {
+            segments_per_span: 4,
+            uv_tiling: 1.0,
+        }
+    }
+
+    fn vector_at(attribute: &[f32], i: u32) -> Vector3<f32> {
+        let i = i as usize * 3;
+        Vector3::new(attribute[i], attribute[i + 1], attribute[i + 2])
+    }
+
+    // every triangle is counter clockwise seen from the side its vertex
+    // normals face, and no vertex is NaN
+    fn assert_ccw(mesh: &tobj::Mesh) {
+        assert!(mesh
+            .positions
+            .iter()
+            .chain(&mesh.normals)
+            .chain(&mesh.texcoords)
+            .all(|c| c.is_finite()));
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|k| vector_at(&mesh.positions, triangle[k]));
+            let normal: Vector3<f32> = triangle.iter().map(|i| vector_at(&mesh.normals, *i)).sum();
+            assert!((b - a).cross(c - a).dot(normal) > 0.0, "{:?}", triangle);
+        }
+    }
+
+    #[test]
+    fn extrude_road() {
+        let spline = spline(&[[0.0, 0.0, 0.0], [0.0, 0.0, -4.0], [3.0, 1.0, -8.0]]);
+        let object = extrude_along_spline(&spline, &Profile::road(2.0), options()).unwrap();
+        let mesh = &object.models[0].mesh;
+
+        // 2 spans of 4 segments, and the last sample
+        assert_eq!(mesh.positions.len(), 9 * 2 * 3);
+        assert_eq!(mesh.indices.len(), 8 * 6);
+        assert_ccw(mesh);
+        // the road faces up
+        assert!(mesh.normals.chunks_exact(3).all(|n| n[1] > 0.5));
+    }
+
+    #[test]
+    fn extrude_circle() {
+        let spline = spline(&[[0.0, 0.0, 0.0], [1.0, 2.0, 0.0], [2.0, 2.0, 1.0]]);
+        let object = extrude_along_spline(&spline, &Profile::circle(0.5, 8), options()).unwrap();
+        let mesh = &object.models[0].mesh;
+
+        // the first profile point is repeated at the end of the ring
+        assert_eq!(mesh.positions.len(), 9 * 9 * 3);
+        assert_eq!(mesh.indices.len(), 8 * 8 * 6);
+        assert_ccw(mesh);
+    }
+
+    #[test]
+    fn extrude_through_repeated_points() {
+        let a = [0.0, 0.0, 0.0];
+        let spline = spline(&[a, a, a, [0.0, 0.0, -4.0]]);
+        // the first two profile points are the same
+        let profile = Profile {
+            points: vec![
+                Vector2::new(-1.0, 0.0),
+                Vector2::new(-1.0, 0.0),
+                Vector2::new(1.0, 0.0),
+            ],
+            closed: false,
+        };
+        let object = extrude_along_spline(&spline, &profile, options()).unwrap();
+        let mesh = &object.models[0].mesh;
+
+        // the 4 samples of the first span and the start of the next one are
+        // one, and the repeated profile point is skipped
+        assert_eq!(mesh.positions.len(), 9 * 2 * 3);
+        assert_eq!(mesh.indices.len(), 8 * 6);
+        assert_eq!(object.sanitize_report, SanitizeReport::default());
+        assert!(mesh.normals.chunks_exact(3).all(|n| n[1] > 0.5));
+        assert!(mesh.positions.iter().all(|c| c.is_finite()));
+
+        // a spline standing on a single point has nothing to extrude
+        let object = extrude_along_spline(&spline[..2], &profile, options()).unwrap();
+        assert!(object.models[0].mesh.positions.is_empty());
+    }
+
+    #[test]
+    fn closed_profile_repeating_its_start() {
+        let spline = spline(&[[0.0, 0.0, 0.0], [0.0, 0.0, -4.0]]);
+        let mut profile = Profile::circle(0.5, 4);
+        profile.points.push(profile.points[0]);
+        profile.points.insert(2, profile.points[2]);
+        let object = extrude_along_spline(&spline, &profile, options()).unwrap();
+        let mesh = &object.models[0].mesh;
+
+        // 4 ring points and the seam, over 5 samples
+        assert_eq!(mesh.positions.len(), 5 * 5 * 3);
+        assert_eq!(mesh.indices.len(), 4 * 4 * 6);
+        assert_ccw(mesh);
+    }
+
+    #[test]
+    fn uv_tiling_must_be_positive() {
+        let spline = spline(&[[0.0, 0.0, 0.0], [0.0, 0.0, -4.0]]);
+        for uv_tiling in [0.0, -1.0, f32::NAN] {
+            let options = ExtrudeOptions {
+                uv_tiling,
+                ..options()
+            };
+            assert!(extrude_along_spline(&spline, &Profile::road(1.0), options).is_err());
+        }
+    }
+}
@@ -0,0 +1,297 @@
+use crate::{binding, camera};
+use cgmath::prelude::*;
+use wgpu::util::DeviceExt;
+
+// Line of a shader replaced by the declaration of the globals uniform
+pub const GLOBALS_IMPORT: &str = "#import lens::globals";
+
+// Line of a shader replaced by dither_fade(position, fade), true for the
+// pixels an instance fading by InstanceRaw::fade leaves out. It declares the
+// globals uniform too when no globals import comes before it.
+pub const DITHER_IMPORT: &str = "#import lens::dither";
+
+// 4x4 Bayer pattern by rows, so that the pixels left out are spread evenly
+const BAYER: [u32; 16] = [0, 8, 2, 10, 12, 4, 14, 6, 3, 11, 1, 9, 15, 7, 13, 5];
+
+// The pixel offset keeps the pattern of the view in targets showing a part of
+// it. BAYER is written in place of {bayer}.
+const DITHER: &str = "fn dither_fade(position: vec2<f32>, fade: f32) -> bool {
+    var bayer: array<u32, 16> = array<u32, 16>({bayer});
+    let pixel = position + globals.pixel_offset;
+    let x = u32(pixel.x) % 4u;
+    let y = u32(pixel.y) % 4u;
+    let threshold = (f32(bayer[y * 4u + x]) + 0.5) / 16.0;
+    if (fade < 0.0) {
+        return threshold >= 1.0 + fade;
+    }
+    return threshold < fade;
+}";
+
+fn dither_function() -> String {
+    let bayer: Vec<String> = BAYER.iter().map(|index| format!("{}u", index)).collect();
+    DITHER.replace("{bayer}", &bayer.join(", "))
+}
+
+// Values shared by all the shaders of a frame
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GlobalsUniform {
+    // seconds since the scene started
+    pub time: f32,
+    // seconds since the previous frame
+    pub delta: f32,
+    pub frame: u32,
+    // Due to uniforms requiring 16 byte (4 float) spacing, we need to use a padding field here
+    pub _padding: u32,
+    // size in pixels of the target being drawn
+    pub resolution: [f32; 2],
+    // pixel of the view at the origin of the target, e.g. the picked one for
+    // the single pixel target of the color picker
+    pub pixel_offset: [f32; 2],
+    pub view_position: [f32; 4],
+    pub view_proj: [[f32; 4]; 4],
+}
+
+pub struct Globals {
+    pub uniform: GlobalsUniform,
+    buffer: wgpu::Buffer,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl Globals {
+    pub fn bind(device: &wgpu::Device) -> Self {
+        let uniform = GlobalsUniform {
+            time: 0.0,
+            delta: 0.0,
+            frame: 0,
+            _padding: 0,
+            resolution: [0.0; 2],
+            pixel_offset: [0.0; 2],
+            view_position: [0.0; 4],
+            view_proj: cgmath::Matrix4::identity().into(),
+        };
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Globals Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("globals_bind_group_layout"),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            label: Some("globals_bind_group"),
+        });
+
+        Self {
+            uniform,
+            buffer,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    // Move to the next frame, once per frame whatever the number of views
+    pub fn update(&mut self, dt: std::time::Duration) {
+        self.uniform.time += dt.as_secs_f32();
+        self.uniform.delta = dt.as_secs_f32();
+        self.uniform.frame = self.uniform.frame.wrapping_add(1);
+    }
+
+    // Upload the globals seen by the view about to be drawn
+    pub fn write(&mut self, queue: &wgpu::Queue, resolution: (u32, u32), camera: &camera::Camera) {
+        self.uniform.resolution = [resolution.0 as f32, resolution.1 as f32];
+        self.uniform.view_position = camera.position().to_homogeneous().into();
+        self.uniform.view_proj = camera.view_proj().into();
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    }
+
+    // Buffer and group of the globals for a target showing a part of the
+    // views, bound in place of bind_group
+    pub(crate) fn create_offset_group(
+        &self,
+        device: &wgpu::Device,
+    ) -> (wgpu::Buffer, wgpu::BindGroup) {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Offset Globals Buffer"),
+            contents: bytemuck::cast_slice(&[self.uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            label: Some("offset_globals_bind_group"),
+        });
+        (buffer, bind_group)
+    }
+
+    // Upload the globals of the view last written, with the pixel of the view
+    // at the origin of the target, to a buffer of create_offset_group
+    pub(crate) fn write_offset(
+        &self,
+        queue: &wgpu::Queue,
+        buffer: &wgpu::Buffer,
+        offset: [f32; 2],
+    ) {
+        let uniform = GlobalsUniform {
+            pixel_offset: offset,
+            ..self.uniform
+        };
+        queue.write_buffer(buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+}
+
+// Replace the import lines of a shader by the globals declaration and the
+// dither function, the globals are declared once before the first of them
+pub fn preprocess(source: &str) -> String {
+    let declaration = format!(
+        "[[block]]
+struct Globals {{
+    time: f32;
+    delta: f32;
+    frame: u32;
+    resolution: vec2<f32>;
+    pixel_offset: vec2<f32>;
+    view_position: vec4<f32>;
+    view_proj: mat4x4<f32>;
+}};
+[[group({}), binding(0)]]
+var<uniform> globals: Globals;",
+        binding::GLOBALS_GROUP
+    );
+    let mut declared = false;
+    source
+        .lines()
+        .map(|line| {
+            let import = line.trim();
+            if import != DITHER_IMPORT && import != GLOBALS_IMPORT {
+                return line.to_string();
+            }
+            let mut expanded = String::new();
+            if !declared {
+                expanded.push_str(&declaration);
+                declared = true;
+            }
+            if import == DITHER_IMPORT {
+                if !expanded.is_empty() {
+                    expanded.push('\n');
+                }
+                expanded.push_str(&dither_function());
+            }
+            expanded
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // WGSL shaders of the tests, checked as wgpu 0.11 would
+    pub(crate) fn validate_wgsl(source: &str) {
+        let source = preprocess(source);
+        let module = naga::front::wgsl::parse_str(&source).unwrap_or_else(|e| {
+            e.emit_to_stderr(&source);
+            panic!("invalid WGSL:\n{}", source)
+        });
+        naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::empty(),
+        )
+        .validate(&module)
+        .unwrap_or_else(|e| panic!("{:?} in\n{}", e, source));
+    }
+
+    // dither_fade on the CPU, for a pixel of the view
+    fn dither_fade(x: u32, y: u32, fade: f32) -> bool {
+        let index = BAYER[(y % 4 * 4 + x % 4) as usize];
+        let threshold = (index as f32 + 0.5) / 16.0;
+        if fade < 0.0 {
+            return threshold >= 1.0 + fade;
+        }
+        threshold < fade
+    }
+
+    // cells of the 4x4 pattern left out at the fade
+    fn discarded(fade: f32) -> Vec<(u32, u32)> {
+        (0..16)
+            .map(|cell| (cell % 4, cell / 4))
+            .filter(|&(x, y)| dither_fade(x, y, fade))
+            .collect()
+    }
+
+    #[test]
+    fn pattern_covers_every_threshold() {
+        let mut indices = BAYER.to_vec();
+        indices.sort_unstable();
+        assert_eq!(indices, (0..16).collect::<Vec<_>>());
+        // the shader reads the same table
+        assert!(dither_function().contains("array<u32, 16>(0u, 8u, 2u, 10u, 12u,"));
+        validate_wgsl(&format!(
+            "{}
+[[stage(fragment)]]
+fn main([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] vec4<f32> {{
+    if (dither_fade(position.xy, 0.5)) {{
+        discard;
+    }}
+    return vec4<f32>(1.0, 1.0, 1.0, 1.0);
+}}",
+            DITHER_IMPORT
+        ));
+    }
+
+    #[test]
+    fn fades_discard_their_share() {
+        for k in 0..=16 {
+            let fade = k as f32 / 16.0;
+            assert_eq!(discarded(fade).len(), k, "fade {}", fade);
+            assert_eq!(discarded(-fade).len(), k, "fade {}", -fade);
+
+            // fading in by -k/16 leaves out the cells fading out by 1 - k/16
+            // keeps, so that cross-fades cover every pixel once
+            let fading_out = discarded(1.0 - fade);
+            let fading_in = discarded(-fade);
+            assert!(fading_in.iter().all(|cell| !fading_out.contains(cell)));
+            assert_eq!(fading_in.len() + fading_out.len(), 16);
+        }
+        // the pattern repeats every 4 pixels
+        assert_eq!(dither_fade(5, 6, 0.5), dither_fade(1, 2, 0.5));
+    }
+
+    #[test]
+    fn globals_are_declared_once() {
+        let declaration = "var<uniform> globals: Globals;";
+        for source in [
+            format!("{}\n{}", GLOBALS_IMPORT, DITHER_IMPORT),
+            format!("{}\n{}", DITHER_IMPORT, GLOBALS_IMPORT),
+            DITHER_IMPORT.to_string(),
+        ] {
+            let source = preprocess(&source);
+            assert_eq!(source.matches(declaration).count(), 1, "{}", source);
+            // before the dither function reading them
+            assert!(source.find(declaration) < source.find("fn dither_fade"));
+        }
+        assert_eq!(preprocess("let a = 1;"), "let a = 1;");
+    }
+}
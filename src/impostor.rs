@@ -0,0 +1,134 @@
+use crate::camera;
+use crate::object::{Object, SanitizeReport};
+use crate::renderer::Model;
+use crate::thumbnail::{self, OffscreenView};
+use anyhow::*;
+use cgmath::*;
+
+#[derive(Debug, Copy, Clone)]
+pub struct ImpostorOptions {
+    // views baked around the vertical axis
+    pub views: u32,
+    // size in pixels of the square cell of each view in the atlas
+    pub cell_size: u32,
+}
+
+impl Default for ImpostorOptions {
+    fn default() -> Self {
+        Self {
+            views: 16,
+            cell_size: 128,
+        }
+    }
+}
+
+// Views of a model baked into an atlas, drawn as a single quad when the model
+// is too far for its geometry to matter
+pub struct Impostor {
+    // views in rows of columns cells, transparent around the model
+    pub atlas: image::RgbaImage,
+    pub views: u32,
+    pub columns: u32,
+    pub rows: u32,
+    pub center: Point3<f32>,
+    // half the side of the quad, the model fits in it from any direction
+    pub radius: f32,
+}
+
+impl Impostor {
+    // Quad of the impostor textured with the atlas, to draw with the shader
+    pub fn object(&self) -> Object {
+        let (c, r) = (self.center, self.radius);
+        let corners = [(-1.0, 1.0), (1.0, 1.0), (1.0, -1.0), (-1.0, -1.0)];
+        let mesh = tobj::Mesh {
+            positions: corners
+                .iter()
+                .flat_map(|(x, y)| [c.x + x * r, c.y + y * r, c.z])
+                .collect(),
+            // the shader finds the center of the impostor in place of the normals
+            normals: corners.iter().flat_map(|_| [c.x, c.y, c.z]).collect(),
+            texcoords: vec![0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0],
+            indices: vec![3, 2, 1, 3, 1, 0],
+            material_id: Some(0),
+            ..Default::default()
+        };
+
+        Object {
+            models: vec![tobj::Model::new(mesh, "impostor".into())],
+            textures: Some(vec![(
+                image::DynamicImage::ImageRgba8(self.atlas.clone()),
+                "impostor".into(),
+                "impostor".into(),
+            )]),
+            sanitize_report: SanitizeReport::default(),
+        }
+    }
+
+    // Shader of the impostor object, set up for the layout of this atlas
+    pub fn shader(&self) -> String {
+        include_str!("shader/impostor.wgsl")
+            .replace("IMPOSTOR_VIEWS", &self.views.to_string())
+            .replace("IMPOSTOR_COLUMNS", &self.columns.to_string())
+            .replace("IMPOSTOR_ROWS", &self.rows.to_string())
+    }
+}
+
+// Render a model from evenly spaced directions around the vertical axis, with
+// an orthographic camera, into the atlas of an impostor
+pub fn bake_impostor(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    model: &Model,
+    options: ImpostorOptions,
+) -> Result<Impostor> {
+    ensure!(
+        options.views > 0 && options.cell_size > 0,
+        "an impostor needs at least one view of one pixel"
+    );
+    ensure!(!model.bounds.is_empty(), "model has no vertices to render");
+
+    let views = options.views;
+    let columns = (views as f32).sqrt().ceil() as u32;
+    let rows = views.div_ceil(columns);
+    let cell = options.cell_size;
+    let center = model.bounds.center();
+    let radius = model.bounds.radius();
+
+    // the bounding sphere fits between the near and far planes of each view
+    let projection = camera::OPENGL_TO_WGPU_MATRIX
+        * ortho(-radius, radius, -radius, radius, radius * 0.5, radius * 3.5);
+    let offscreen_views: Vec<OffscreenView> = (0..views)
+        .map(|view| {
+            let angle = std::f32::consts::TAU * view as f32 / views as f32;
+            let position = center + Vector3::new(angle.cos(), 0.0, angle.sin()) * radius * 2.0;
+            OffscreenView {
+                position,
+                view_proj: projection * Matrix4::look_at_rh(position, center, Vector3::unit_y()),
+                viewport: camera::Viewport::new(
+                    ((view % columns) * cell) as f32,
+                    ((view / columns) * cell) as f32,
+                    cell as f32,
+                    cell as f32,
+                ),
+            }
+        })
+        .collect();
+
+    let atlas = thumbnail::render_views(
+        device,
+        queue,
+        model,
+        winit::dpi::PhysicalSize::new(columns * cell, rows * cell),
+        &offscreen_views,
+        wgpu::Color::TRANSPARENT,
+    )?;
+
+    Ok(Impostor {
+        atlas,
+        views,
+        columns,
+        rows,
+        center,
+        radius,
+    })
+}
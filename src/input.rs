@@ -0,0 +1,348 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::io::Write;
+use std::path::Path;
+use winit::dpi::PhysicalPosition;
+use winit::event::{DeviceEvent, ElementState, KeyboardInput, MouseScrollDelta, VirtualKeyCode};
+
+// Keys the scene reacts to, by the name they are recorded with
+const RECORDED_KEYS: [(VirtualKeyCode, &str); 16] = [
+    (VirtualKeyCode::W, "W"),
+    (VirtualKeyCode::A, "A"),
+    (VirtualKeyCode::S, "S"),
+    (VirtualKeyCode::D, "D"),
+    (VirtualKeyCode::F, "F"),
+    (VirtualKeyCode::H, "H"),
+    (VirtualKeyCode::P, "P"),
+    (VirtualKeyCode::Q, "Q"),
+    (VirtualKeyCode::T, "T"),
+    (VirtualKeyCode::V, "V"),
+    (VirtualKeyCode::Up, "Up"),
+    (VirtualKeyCode::Down, "Down"),
+    (VirtualKeyCode::Left, "Left"),
+    (VirtualKeyCode::Right, "Right"),
+    (VirtualKeyCode::Space, "Space"),
+    (VirtualKeyCode::LShift, "LShift"),
+];
+
+// Device input the scene reacts to, in a form that can be written to a file
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum InputEvent {
+    Key { key: VirtualKeyCode, pressed: bool },
+    Button { button: u32, pressed: bool },
+    MouseMotion { dx: f64, dy: f64 },
+    // scroll in lines, or in pixels for touchpads
+    Scroll { lines: bool, amount: f64 },
+}
+
+fn state_name(pressed: bool) -> &'static str {
+    if pressed {
+        "pressed"
+    } else {
+        "released"
+    }
+}
+
+fn to_state(pressed: bool) -> ElementState {
+    if pressed {
+        ElementState::Pressed
+    } else {
+        ElementState::Released
+    }
+}
+
+impl InputEvent {
+    // None for the events the scene ignores
+    pub fn from_device_event(event: &DeviceEvent) -> Option<Self> {
+        match event {
+            DeviceEvent::Key(KeyboardInput {
+                virtual_keycode: Some(key),
+                state,
+                ..
+            }) => RECORDED_KEYS
+                .iter()
+                .any(|(recorded, _)| recorded == key)
+                .then(|| InputEvent::Key {
+                    key: *key,
+                    pressed: *state == ElementState::Pressed,
+                }),
+            DeviceEvent::Button { button, state } => Some(InputEvent::Button {
+                button: *button,
+                pressed: *state == ElementState::Pressed,
+            }),
+            DeviceEvent::MouseMotion { delta } => Some(InputEvent::MouseMotion {
+                dx: delta.0,
+                dy: delta.1,
+            }),
+            DeviceEvent::MouseWheel { delta } => Some(match delta {
+                MouseScrollDelta::LineDelta(_, lines) => InputEvent::Scroll {
+                    lines: true,
+                    amount: *lines as f64,
+                },
+                MouseScrollDelta::PixelDelta(position) => InputEvent::Scroll {
+                    lines: false,
+                    amount: position.y,
+                },
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn to_device_event(self) -> DeviceEvent {
+        match self {
+            #[allow(deprecated)]
+            InputEvent::Key { key, pressed } => DeviceEvent::Key(KeyboardInput {
+                scancode: 0,
+                state: to_state(pressed),
+                virtual_keycode: Some(key),
+                modifiers: Default::default(),
+            }),
+            InputEvent::Button { button, pressed } => DeviceEvent::Button {
+                button,
+                state: to_state(pressed),
+            },
+            InputEvent::MouseMotion { dx, dy } => DeviceEvent::MouseMotion { delta: (dx, dy) },
+            InputEvent::Scroll {
+                lines: true,
+                amount,
+            } => DeviceEvent::MouseWheel {
+                delta: MouseScrollDelta::LineDelta(0.0, amount as f32),
+            },
+            InputEvent::Scroll {
+                lines: false,
+                amount,
+            } => DeviceEvent::MouseWheel {
+                delta: MouseScrollDelta::PixelDelta(PhysicalPosition::new(0.0, amount)),
+            },
+        }
+    }
+
+    // One line of a recording, without the frame index. Floats are written
+    // with all their digits so that playback is exact.
+    fn to_line(self) -> String {
+        match self {
+            InputEvent::Key { key, pressed } => {
+                let name = RECORDED_KEYS
+                    .iter()
+                    .find(|(recorded, _)| *recorded == key)
+                    .map_or("?", |(_, name)| name);
+                format!("key {} {}", name, state_name(pressed))
+            }
+            InputEvent::Button { button, pressed } => {
+                format!("button {} {}", button, state_name(pressed))
+            }
+            InputEvent::MouseMotion { dx, dy } => format!("motion {} {}", dx, dy),
+            InputEvent::Scroll {
+                lines: true,
+                amount,
+            } => format!("scroll lines {}", amount),
+            InputEvent::Scroll {
+                lines: false,
+                amount,
+            } => format!("scroll pixels {}", amount),
+        }
+    }
+
+    fn parse(words: &[&str]) -> Result<Self> {
+        let pressed = |word: &str| match word {
+            "pressed" => Ok(true),
+            "released" => Ok(false),
+            _ => Err(anyhow!("unknown key state {:?}", word)),
+        };
+        match words {
+            ["key", name, state] => {
+                let key = RECORDED_KEYS
+                    .iter()
+                    .find(|(_, recorded)| recorded == name)
+                    .map(|(key, _)| *key)
+                    .ok_or_else(|| anyhow!("unknown key {:?}", name))?;
+                Ok(InputEvent::Key {
+                    key,
+                    pressed: pressed(state)?,
+                })
+            }
+            ["button", button, state] => Ok(InputEvent::Button {
+                button: button.parse()?,
+                pressed: pressed(state)?,
+            }),
+            ["motion", dx, dy] => Ok(InputEvent::MouseMotion {
+                dx: dx.parse()?,
+                dy: dy.parse()?,
+            }),
+            ["scroll", unit @ ("lines" | "pixels"), amount] => Ok(InputEvent::Scroll {
+                lines: *unit == "lines",
+                amount: amount.parse()?,
+            }),
+            _ => bail!("unknown input event {:?}", words.join(" ")),
+        }
+    }
+}
+
+// Writes the input of each frame to a file, one event per line prefixed by
+// the index of the frame it was applied on
+pub struct InputRecorder {
+    writer: std::io::BufWriter<std::fs::File>,
+    frame: u64,
+}
+
+impl InputRecorder {
+    pub fn create<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        Ok(Self {
+            writer: std::io::BufWriter::new(std::fs::File::create(path)?),
+            frame: 0,
+        })
+    }
+
+    pub fn record(&mut self, event: &DeviceEvent) -> std::io::Result<()> {
+        match InputEvent::from_device_event(event) {
+            Some(event) => writeln!(self.writer, "{} {}", self.frame, event.to_line()),
+            None => Ok(()),
+        }
+    }
+
+    // Called once the scene was updated with the input of the frame
+    pub fn next_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+// Feeds a recording back, frame by frame
+pub struct InputPlayback {
+    // events sorted by frame
+    events: Vec<(u64, InputEvent)>,
+    next: usize,
+    frame: u64,
+}
+
+impl InputPlayback {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut events = Vec::new();
+        for (number, line) in std::fs::read_to_string(path)?.lines().enumerate() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.is_empty() {
+                continue;
+            }
+            let frame = words[0]
+                .parse()
+                .with_context(|| format!("line {}: bad frame index", number + 1))?;
+            let event =
+                InputEvent::parse(&words[1..]).with_context(|| format!("line {}", number + 1))?;
+            events.push((frame, event));
+        }
+        events.sort_by_key(|(frame, _)| *frame);
+
+        Ok(Self {
+            events,
+            next: 0,
+            frame: 0,
+        })
+    }
+
+    // Events to apply before updating the current frame, then move to the next one
+    pub fn next_frame(&mut self) -> Vec<DeviceEvent> {
+        let mut frame_events = Vec::new();
+        while let Some((frame, event)) = self.events.get(self.next) {
+            if *frame > self.frame {
+                break;
+            }
+            frame_events.push(event.to_device_event());
+            self.next += 1;
+        }
+        self.frame += 1;
+        frame_events
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.events.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(event: InputEvent) -> InputEvent {
+        let line = event.to_line();
+        let words: Vec<&str> = line.split_whitespace().collect();
+        InputEvent::parse(&words).unwrap()
+    }
+
+    #[test]
+    fn lines_round_trip() {
+        let mut events: Vec<InputEvent> = RECORDED_KEYS
+            .iter()
+            .flat_map(|(key, _)| {
+                [true, false].map(|pressed| InputEvent::Key { key: *key, pressed })
+            })
+            .collect();
+        events.extend([
+            InputEvent::Button {
+                button: 3,
+                pressed: true,
+            },
+            InputEvent::Button {
+                button: 1,
+                pressed: false,
+            },
+            // every digit of the floats is kept
+            InputEvent::MouseMotion {
+                dx: 0.1 + 0.2,
+                dy: -1.0 / 3.0,
+            },
+            InputEvent::Scroll {
+                lines: true,
+                amount: -2.0,
+            },
+            InputEvent::Scroll {
+                lines: false,
+                amount: 12.75,
+            },
+        ]);
+        for event in events {
+            assert_eq!(round_trip(event), event);
+            assert_eq!(
+                InputEvent::from_device_event(&event.to_device_event()),
+                Some(event)
+            );
+        }
+        assert!(InputEvent::parse(&["key", "Escape", "pressed"]).is_err());
+        assert!(InputEvent::parse(&["key", "W", "held"]).is_err());
+    }
+
+    // Keys named in the source, after VirtualKeyCode::
+    fn keys_in(source: &str) -> Vec<&str> {
+        source
+            .split("VirtualKeyCode::")
+            .skip(1)
+            .map(|rest| {
+                let end = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric())
+                    .unwrap_or(rest.len());
+                &rest[..end]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn scene_keys_are_recorded() {
+        let lib = include_str!("lib.rs");
+        // the device input handlers of the views and the scene
+        let handlers = lib
+            .split("fn input(&mut self, event: &DeviceEvent)")
+            .skip(1)
+            .map(|rest| rest.split("\n    fn ").next().unwrap());
+        let camera = include_str!("camera.rs");
+        let keys: Vec<&str> = handlers.chain([camera]).flat_map(keys_in).collect();
+        assert!(keys.contains(&"F") && keys.contains(&"W"));
+        for key in keys {
+            assert!(
+                RECORDED_KEYS.iter().any(|(_, name)| *name == key),
+                "{} isn't recorded",
+                key
+            );
+        }
+    }
+}
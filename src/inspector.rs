@@ -0,0 +1,253 @@
+use crate::texture;
+use anyhow::*;
+use wgpu::util::DeviceExt;
+
+const COLOR_TEXTURE: &str = "[[group(0), binding(0)]]
+var t_inspect: texture_2d<f32>;
+fn load(coords: vec2<i32>) -> vec4<f32> {
+    return textureLoad(t_inspect, coords, 0);
+}
+";
+
+const DEPTH_TEXTURE: &str = "[[group(0), binding(0)]]
+var t_inspect: texture_depth_2d;
+fn load(coords: vec2<i32>) -> vec4<f32> {
+    return vec4<f32>(vec3<f32>(textureLoad(t_inspect, coords, 0)), 1.0);
+}
+";
+
+// What part of a texture is shown and how its values are mapped to colors
+#[derive(Debug, Copy, Clone)]
+pub struct InspectOptions {
+    pub mip_level: u32,
+    // array layer, or face of a cube texture in the +X, -X, +Y, -Y, +Z, -Z order
+    pub layer: u32,
+    // r, g, b and a shown, a single one is shown as grey
+    pub channels: [bool; 4],
+    // values drawn black and white, the ones between are stretched
+    pub range: (f32, f32),
+    // the texture is a depth one, e.g. a shadow map or a depth target
+    pub depth: bool,
+    // near and far planes of the projection the depth was drawn with, to show
+    // the distance between them instead of the raw depth
+    pub linearize: Option<(f32, f32)>,
+}
+
+impl Default for InspectOptions {
+    fn default() -> Self {
+        Self {
+            mip_level: 0,
+            layer: 0,
+            channels: [true; 4],
+            range: (0.0, 1.0),
+            depth: false,
+            linearize: None,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct InspectUniform {
+    mask: [f32; 4],
+    range: [f32; 2],
+    planes: [f32; 2],
+    grey: f32,
+    _padding: [f32; 3],
+}
+
+// Draws textures over rectangles of a target, to look at what the GPU holds
+// in its shadow maps, G-buffers or mips. Float and depth textures can be shown,
+// texels are read as is without filtering.
+pub struct TextureInspector {
+    color: (wgpu::BindGroupLayout, wgpu::RenderPipeline),
+    depth: (wgpu::BindGroupLayout, wgpu::RenderPipeline),
+}
+
+impl TextureInspector {
+    // Inspector drawing to targets of the given format
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        Self {
+            color: Self::create_pipeline(device, format, false),
+            depth: Self::create_pipeline(device, format, true),
+        }
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        depth: bool,
+    ) -> (wgpu::BindGroupLayout, wgpu::RenderPipeline) {
+        let sample_type = match depth {
+            true => wgpu::TextureSampleType::Depth,
+            false => wgpu::TextureSampleType::Float { filterable: false },
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("inspect_bind_group_layout"),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Inspect Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Inspect Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                format!(
+                    "{}\n{}",
+                    if depth { DEPTH_TEXTURE } else { COLOR_TEXTURE },
+                    include_str!("shader/inspect.wgsl")
+                )
+                .into(),
+            ),
+        });
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Inspect Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+        });
+
+        (bind_group_layout, render_pipeline)
+    }
+
+    // Draw a mip and layer of the texture into the rectangle (x, y, width,
+    // height) of the target, in pixels, over what it already shows. The
+    // texture needs the TEXTURE_BINDING usage and the mip level must exist.
+    pub fn draw(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &texture::Texture,
+        target: &wgpu::TextureView,
+        rect: (f32, f32, f32, f32),
+        options: &InspectOptions,
+    ) -> Result<()> {
+        let (x, y, width, height) = rect;
+        ensure!(
+            width > 0.0 && height > 0.0,
+            "inspected rectangle must not be empty"
+        );
+        ensure!(
+            options.layer < texture.size.depth_or_array_layers,
+            "texture has no layer {}",
+            options.layer
+        );
+        ensure!(
+            options.range.0 != options.range.1,
+            "inspected range must not be empty"
+        );
+        let shown = options.channels.iter().filter(|shown| **shown).count();
+        ensure!(shown > 0, "no channel to inspect");
+        if let Some((near, far)) = options.linearize {
+            ensure!(
+                options.depth && 0.0 < near && near < far,
+                "linearized depth needs a depth texture and planes with 0 < near < far"
+            );
+        }
+
+        let view = texture.texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("inspect_view"),
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            aspect: match options.depth {
+                true => wgpu::TextureAspect::DepthOnly,
+                false => wgpu::TextureAspect::All,
+            },
+            base_mip_level: options.mip_level,
+            mip_level_count: std::num::NonZeroU32::new(1),
+            base_array_layer: options.layer,
+            array_layer_count: std::num::NonZeroU32::new(1),
+            ..Default::default()
+        });
+        let mut mask = [0.0; 4];
+        for (mask, shown) in mask.iter_mut().zip(options.channels) {
+            *mask = if shown { 1.0 } else { 0.0 };
+        }
+        let (near, far) = options.linearize.unwrap_or((0.0, 0.0));
+        let uniform = InspectUniform {
+            mask,
+            range: [options.range.0, options.range.1],
+            planes: [near, far],
+            grey: if shown == 1 { 1.0 } else { 0.0 },
+            _padding: [0.0; 3],
+        };
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Inspect Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let (bind_group_layout, render_pipeline) = match options.depth {
+            true => &self.depth,
+            false => &self.color,
+        };
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("inspect_bind_group"),
+        });
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Inspect Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_viewport(x, y, width, height, 0.0, 1.0);
+        render_pass.set_pipeline(render_pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+        Ok(())
+    }
+}
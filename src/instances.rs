@@ -0,0 +1,100 @@
+use crate::bounds::Aabb;
+use crate::renderer::InstanceRaw;
+use std::sync::{Arc, Mutex};
+
+// Instances of an object that can be changed while the scene runs, e.g. from a
+// plugin or another thread. Changes are uploaded once, at the next frame.
+#[derive(Debug, Clone)]
+pub struct InstanceHandle {
+    state: Arc<Mutex<InstanceState>>,
+}
+
+#[derive(Debug)]
+struct InstanceState {
+    instances: Vec<InstanceRaw>,
+    // changed since the scene last uploaded them
+    dirty: bool,
+}
+
+impl InstanceHandle {
+    pub(crate) fn new(instances: Vec<InstanceRaw>) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(InstanceState {
+                instances,
+                dirty: false,
+            })),
+        }
+    }
+
+    // Replace all the instances, there can be more or fewer than before
+    pub fn set(&self, instances: Vec<InstanceRaw>) {
+        self.modify(|current| *current = instances);
+    }
+
+    // Change the instances in place, e.g. their transforms or their fade
+    pub fn modify<F: FnOnce(&mut Vec<InstanceRaw>)>(&self, change: F) {
+        let mut state = self.state.lock().unwrap();
+        change(&mut state.instances);
+        state.dirty = true;
+    }
+
+    // Instances changed since the last call, None when they are the same
+    pub(crate) fn take_changes(&self) -> Option<Vec<InstanceRaw>> {
+        let mut state = self.state.lock().unwrap();
+        if !state.dirty {
+            return None;
+        }
+        state.dirty = false;
+        Some(state.instances.clone())
+    }
+}
+
+// World bounds of a model drawn at each of the instances
+pub(crate) fn world_bounds(bounds: &Aabb, instances: &[InstanceRaw]) -> Aabb {
+    instances
+        .iter()
+        .fold(Aabb::empty(), |world_bounds, instance| {
+            world_bounds.union(&bounds.transform(&instance.model.into()))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::*;
+
+    fn instance(x: f32) -> InstanceRaw {
+        InstanceRaw {
+            model: Matrix4::from_translation(Vector3::new(x, 0.0, 0.0)).into(),
+            normal: Matrix3::identity().into(),
+            phase: 0.0,
+            fade: 0.0,
+        }
+    }
+
+    #[test]
+    fn changes_are_taken_once() {
+        let handle = InstanceHandle::new(vec![instance(0.0)]);
+        assert!(handle.take_changes().is_none());
+
+        // clones change the same instances
+        handle.clone().modify(|instances| instances[0].fade = 0.5);
+        handle.set(vec![instance(1.0), instance(2.0)]);
+        let changes = handle.take_changes().unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[1].model[3][0], 2.0);
+        assert!(handle.take_changes().is_none());
+    }
+
+    #[test]
+    fn bounds_of_every_instance() {
+        let bounds = Aabb {
+            min: Point3::new(-1.0, -1.0, -1.0),
+            max: Point3::new(1.0, 1.0, 1.0),
+        };
+        let world = world_bounds(&bounds, &[instance(-5.0), instance(5.0)]);
+        assert_eq!(world.min, Point3::new(-6.0, -1.0, -1.0));
+        assert_eq!(world.max, Point3::new(6.0, 1.0, 1.0));
+        assert!(world_bounds(&bounds, &[]).is_empty());
+    }
+}
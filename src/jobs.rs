@@ -0,0 +1,69 @@
+use rayon::prelude::*;
+use std::panic::AssertUnwindSafe;
+use std::sync::OnceLock;
+
+// Scope of the jobs started by job_scope, they can borrow from the caller
+pub type JobScope<'scope> = rayon::Scope<'scope>;
+
+// Worker threads shared by lens and the application, one per core, so that
+// their jobs don't compete for the machine
+fn pool() -> &'static rayon::ThreadPool {
+    static POOL: OnceLock<rayon::ThreadPool> = OnceLock::new();
+    POOL.get_or_init(|| {
+        rayon::ThreadPoolBuilder::new()
+            .thread_name(|index| format!("lens-worker-{}", index))
+            .build()
+            .expect("failed to start the lens worker threads")
+    })
+}
+
+// Run a job on the workers without waiting for it. A panicking job is logged
+// instead of aborting the process, what it owns is dropped, e.g. the sender of
+// its result.
+pub fn spawn_job<F: FnOnce() + Send + 'static>(job: F) {
+    pool().spawn(move || {
+        if std::panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+            log::error!("a lens worker job panicked");
+        }
+    });
+}
+
+// Run jobs on the workers and wait until all of them are done
+pub fn job_scope<'scope, F, R>(jobs: F) -> R
+where
+    F: FnOnce(&JobScope<'scope>) -> R + Send,
+    R: Send,
+{
+    pool().scope(jobs)
+}
+
+// Map the items on the workers, keeping their order
+pub fn map_jobs<T, U, F>(items: Vec<T>, job: F) -> Vec<U>
+where
+    T: Send,
+    U: Send,
+    F: Fn(T) -> U + Send + Sync,
+{
+    pool().install(|| items.into_par_iter().map(job).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn panicking_job_drops_its_sender() {
+        let (sender, receiver) = mpsc::channel::<()>();
+        spawn_job(move || {
+            let _sender = sender;
+            panic!("bad shader");
+        });
+        // the workers are still there for the next jobs
+        let (done, finished) = mpsc::channel();
+        spawn_job(move || done.send(()).unwrap());
+        finished.recv().unwrap();
+        assert_eq!(receiver.recv(), Err(mpsc::RecvError));
+        assert_eq!(receiver.try_recv(), Err(mpsc::TryRecvError::Disconnected));
+    }
+}
@@ -1,136 +1,154 @@
+mod background;
+mod bake;
+mod binding;
+mod bounds;
+mod bvh;
 mod camera;
+mod context;
+mod debug_view;
+mod extrude;
+mod globals;
+mod impostor;
+mod input;
+mod inspector;
+mod instances;
+mod jobs;
 mod light;
+mod material_graph;
 mod object;
+mod panorama;
+mod picker;
+mod plugin;
+mod present;
+mod quality;
 mod renderer;
+mod screenshot;
+mod sh;
+mod spatial;
+mod stats;
 mod texture;
+mod thumbnail;
+mod unwrap;
+mod validation;
+mod video;
 
-pub use object::Object;
-pub use renderer::InstanceRaw;
+pub use background::Background;
+pub use bake::{bake_lightmap, bake_maps, BakeLight, BakeOptions, BakedMaps, LightmapOptions};
+pub use binding::{CAMERA_GROUP, GLOBALS_GROUP, LIGHT_GROUP, MATERIAL_GROUP};
+pub use bounds::{Aabb, Frustum};
+pub use camera::{Camera, Eye, Ray, StereoEyes, Viewport};
+pub use context::{Capabilities, GpuContext};
+pub use debug_view::{DebugPalette, DebugView};
+pub use extrude::{extrude_along_spline, ExtrudeOptions, Profile, SplinePoint};
+pub use globals::{GlobalsUniform, DITHER_IMPORT, GLOBALS_IMPORT};
+pub use impostor::{bake_impostor, Impostor, ImpostorOptions};
+pub use input::{InputEvent, InputPlayback, InputRecorder};
+pub use inspector::{InspectOptions, TextureInspector};
+pub use instances::InstanceHandle;
+pub use jobs::{job_scope, map_jobs, spawn_job, JobScope};
+pub use material_graph::{MaterialGraph, MaterialNode, NodeId};
+pub use object::{Object, SanitizeReport, WindingReport};
+pub use picker::PickedColor;
+pub use plugin::{PluginEye, PluginSetup, PluginTarget, RenderPlugin};
+pub use present::PresentPolicy;
+pub use quality::{QualityPreset, QualitySettings};
 use renderer::{DrawModel, ModelRenderer};
+pub use renderer::{InstanceRaw, Model};
+pub use sh::{project_cubemap, SphericalHarmonics};
+pub use spatial::SpatialIndex;
+pub use stats::{NodeInfo, SceneStats};
+pub use texture::Texture;
+pub use thumbnail::{render_thumbnail, render_turntable};
+pub use unwrap::{unwrap_uvs, UnwrapOptions, UvAtlas};
+pub use validation::{validate_dir, validate_file, AssetReport, Problem};
+pub use video::{FrameSource, GifSource, VideoTexture};
 use winit::{
-    dpi::PhysicalPosition,
     event::*,
     event_loop::{ControlFlow, EventLoop},
     window::Window,
     window::WindowBuilder,
 };
 
-struct Scene {
+// margin of the spatial index, in scene units, that nodes can move by before
+// the index changes
+const SPATIAL_MARGIN: f32 = 0.1;
+
+// A window the scene is drawn into, with its own camera
+struct View {
     surface: wgpu::Surface,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
     size: winit::dpi::PhysicalSize<u32>,
     depth_texture: texture::Texture,
+    depth_format: wgpu::TextureFormat,
     mouse_pressed: bool,
-    // camera & light binders
+    // last position of the cursor in the window
+    cursor: winit::dpi::PhysicalPosition<f64>,
     camera_binder: camera::Camera,
-    light_binder: light::Light,
-    // models to draw
-    // renderers for each model to draw
-    model_renderers: Vec<ModelRenderer>,
+    // target drawn to instead of the surface, at the logical resolution or
+    // the render scale
+    logical_target: Option<present::LogicalTarget>,
+    // fragment counts of the overdraw view
+    overdraw_target: Option<debug_view::OverdrawTarget>,
+    // declared last so that the surface is dropped before its window
+    window: Window,
 }
 
-impl<'a> Scene {
-    // Creating some of the wgpu types requires async code
-    async fn new(window: &Window, lens_objects: &mut Vec<LensObject<'a>>) -> Scene {
+impl View {
+    fn new(
+        context: &GpuContext,
+        surface: wgpu::Surface,
+        window: Window,
+        format: wgpu::TextureFormat,
+        depth_format: wgpu::TextureFormat,
+    ) -> View {
         let size = window.inner_size();
 
-        // The instance is a handle to our GPU
-        // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
-        let instance = wgpu::Instance::new(wgpu::Backends::all());
-        let surface = unsafe { instance.create_surface(window) };
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
-            .await
-            .unwrap();
-
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::default(),
-                    label: None,
-                },
-                None, // Trace path
-            )
-            .await
-            .unwrap();
-
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface.get_preferred_format(&adapter).unwrap(),
+            format,
             width: size.width,
             height: size.height,
             present_mode: wgpu::PresentMode::Fifo,
         };
-        surface.configure(&device, &config);
+        surface.configure(&context.device, &config);
 
-        let depth_texture =
-            texture::Texture::create_depth_texture(&device, &config, "depth_texture");
+        let depth_texture = texture::Texture::create_depth_texture_with_format(
+            &context.device,
+            &config,
+            depth_format,
+            "depth_texture",
+        );
 
         // create the camera
-        let camera_binder = camera::Camera::new(&device, &config);
-
-        // create light bind_group_layout and bind group
-        let light_uniform = light::LightUniform {
-            position: [2.0, 2.0, 2.0],
-            _padding: 0,
-            color: [0.2, 0.5, 0.7],
-        };
-        let light_binder = light::Light::bind(&device, light_uniform);
-
-        let mut model_renderers = Vec::new();
-        for _ in 0..lens_objects.len() {
-            let object = lens_objects.pop().unwrap();
-            let (instances_data, instances_len) =
-                if let Some((data, len)) = object.instances.clone() {
-                    (Some(data.clone()), Some(len.clone()))
-                } else {
-                    (None, None)
-                };
-            let cube_renderer = ModelRenderer::new_renderer(
-                renderer::Model::load(&device, &queue, object.object).unwrap(),
-                &device,
-                &config,
-                &camera_binder,
-                &light_binder,
-                std::borrow::Cow::Borrowed(object.shader_file),
-                instances_data,
-                instances_len,
-            );
-            model_renderers.push(cube_renderer);
-        }
+        let camera_binder = camera::Camera::new(&context.device, &config);
 
         Self {
             surface,
-            device,
-            queue,
             config,
             size,
             depth_texture,
+            depth_format,
             mouse_pressed: false,
+            cursor: winit::dpi::PhysicalPosition::new(0.0, 0.0),
             camera_binder,
-            light_binder,
-            model_renderers,
+            logical_target: None,
+            overdraw_target: None,
+            window,
         }
     }
 
-    fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+    fn resize(&mut self, device: &wgpu::Device, new_size: winit::dpi::PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
-            self.camera_binder
-                .projection
-                .resize(new_size.width, new_size.height);
             self.size = new_size;
             self.config.width = new_size.width;
             self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
-            self.depth_texture =
-                texture::Texture::create_depth_texture(&self.device, &self.config, "depth_texture");
+            self.surface.configure(device, &self.config);
+            self.depth_texture = texture::Texture::create_depth_texture_with_format(
+                device,
+                &self.config,
+                self.depth_format,
+                "depth_texture",
+            );
         }
     }
 
@@ -166,31 +184,777 @@ impl<'a> Scene {
             _ => false,
         }
     }
+}
+
+// What the Lens sets the scene up with
+struct SceneOptions {
+    panorama: Option<(image::DynamicImage, bool)>,
+    stereo_separation: Option<f32>,
+    logical_resolution: Option<((u32, u32), present::PresentPolicy)>,
+    quality: QualitySettings,
+    background: Background,
+    ambient: Option<([f32; 3], [f32; 3])>,
+    depth_format: Option<wgpu::TextureFormat>,
+    debug_palette: DebugPalette,
+}
+
+struct Scene {
+    context: GpuContext,
+    // one view per window, they all share the models and their pipelines
+    views: Vec<View>,
+    // view receiving the keyboard and mouse input
+    focused_view: usize,
+    // layouts and placeholder of the canonical bind groups
+    registry: binding::BindingRegistry,
+    light_binder: light::Light,
+    // time, frame and view values shared by all the shaders
+    globals: globals::Globals,
+    // draws the logical targets of the views to their windows, when there is a
+    // logical resolution or a render scale
+    presenter: Option<present::Presenter>,
+    quality: QualitySettings,
+    dynamic_resolution: quality::DynamicResolution,
+    background_renderer: background::BackgroundRenderer,
+    // equirectangular image drawn behind the models, instead of the background
+    panorama_renderer: Option<ModelRenderer>,
+    // the H key switches between the panorama and the background
+    show_panorama: bool,
+    // renderers for each model to draw
+    model_renderers: Vec<ModelRenderer>,
+    // world bounds of the model renderers, keyed by their index
+    spatial_index: SpatialIndex,
+    // instances changed while the scene runs, with the index of their renderer
+    instance_handles: Vec<(usize, InstanceHandle)>,
+    // videos played into a material, by renderer and material index
+    videos: Vec<(usize, usize, video::BoxedVideo)>,
+    // the T key stops the time of the scene, the cameras still move
+    paused: bool,
+    // reads back the color under the cursor, when enabled
+    picker: Option<picker::ColorPicker>,
+    // window of the view the P key captures the next time it is drawn
+    screenshot_request: Option<winit::window::WindowId>,
+    // captured in the last frame, saved at the next one
+    screenshot: Option<screenshot::Screenshot>,
+    // of the depth targets of every view, the pipelines are built for it
+    depth_format: wgpu::TextureFormat,
+    debug_view: DebugView,
+    debug_palette: DebugPalette,
+    // shows the overdraw counts, once the overdraw view is used
+    heatmap: Option<debug_view::Heatmap>,
+    plugins: Vec<Box<dyn RenderPlugin>>,
+}
+
+impl<'a> Scene {
+    // Creating some of the wgpu types requires async code
+    async fn new(
+        windows: Vec<Window>,
+        lens_objects: &mut Vec<LensObject<'a>>,
+        options: SceneOptions,
+    ) -> Scene {
+        let SceneOptions {
+            panorama,
+            stereo_separation,
+            logical_resolution,
+            quality,
+            background,
+            ambient,
+            depth_format,
+            debug_palette,
+        } = options;
+        let mut windows = windows.into_iter();
+        let first_window = windows.next().unwrap();
+        let (context, first_surface) = GpuContext::new(&first_window).await;
+
+        // pipelines are shared by all the views, so they all use the format
+        // preferred by the first one
+        let format = context.surface_format;
+        log::info!("rendering with {:?}", context.adapter.get_info());
+        let depth_format = match depth_format {
+            Some(depth_format) if context.capabilities().supports_depth_format(depth_format) => {
+                depth_format
+            }
+            Some(depth_format) => {
+                log::warn!(
+                    "{:?} isn't supported for depth targets, using {:?}",
+                    depth_format,
+                    texture::Texture::DEPTH_FORMAT
+                );
+                texture::Texture::DEPTH_FORMAT
+            }
+            None => texture::Texture::DEPTH_FORMAT,
+        };
+        let mut views = vec![View::new(
+            &context,
+            first_surface,
+            first_window,
+            format,
+            depth_format,
+        )];
+        for window in windows {
+            let surface = context.create_surface(&window);
+            views.push(View::new(&context, surface, window, format, depth_format));
+        }
+
+        // create light bind_group_layout and bind group
+        let light_color = [0.2, 0.5, 0.7];
+        // the given ambient, else the one of the sky, else a flat one
+        let (sky_color, ground_color) = ambient
+            .or_else(|| background.ambient())
+            .unwrap_or_else(|| light::flat_ambient(light_color));
+        let light_uniform = light::LightUniform {
+            position: [2.0, 2.0, 2.0],
+            _padding: 0,
+            color: light_color,
+            _padding2: 0,
+            sky_color,
+            _padding3: 0,
+            ground_color,
+            _padding4: 0,
+        };
+        let light_binder = light::Light::bind(&context.device, light_uniform);
+        let globals = globals::Globals::bind(&context.device);
+        let registry = binding::BindingRegistry::new(&context.device);
+
+        let presenter = logical_resolution.map(|(size, policy)| {
+            for view in &mut views {
+                view.camera_binder.projection.resize(size.0, size.1);
+            }
+            present::Presenter::new(
+                &context.device,
+                format,
+                Some(size),
+                policy,
+                quality.render_scale,
+            )
+        });
+
+        if let Some(separation) = stereo_separation {
+            for view in &mut views {
+                view.camera_binder
+                    .enable_stereo(&context.device, separation);
+            }
+        }
+
+        // a panorama is looked at from its center, the cameras can only rotate
+        // unless it only lights up the surroundings
+        if let Some((_, rotation_only)) = panorama {
+            for view in &mut views {
+                view.camera_binder.camera_controller.rotation_only = rotation_only;
+            }
+        }
+
+        let inputs = renderer::PipelineInputs {
+            device: &context.device,
+            queue: &context.queue,
+            color_format: format,
+            depth_format,
+            registry: &registry,
+            camera: &views[0].camera_binder,
+            light: &light_binder,
+            globals: &globals,
+        };
+        // e.g. a sky without a sun direction
+        let background_renderer = background::BackgroundRenderer::new(background, &inputs)
+            .or_else(|e| {
+                log::error!("{}, the default background is drawn instead", e);
+                background::BackgroundRenderer::new(Background::default(), &inputs)
+            })
+            .unwrap();
+        let panorama_renderer =
+            panorama.map(|(image, _)| panorama::panorama_renderer(image, &inputs).unwrap());
+
+        let mut model_renderers = Vec::new();
+        let mut spatial_index = SpatialIndex::new(SPATIAL_MARGIN);
+        for _ in 0..lens_objects.len() {
+            let object = lens_objects.pop().unwrap();
+            let (instances_data, instances_len) =
+                if let Some((data, len)) = object.instances.clone() {
+                    (Some(data.clone()), Some(len))
+                } else {
+                    (None, None)
+                };
+            let model =
+                renderer::Model::load(&context.device, &context.queue, object.object).unwrap();
+            // instanced models are where their instances put them
+            let world_bounds = match object.instances.as_ref() {
+                Some((data, len)) => instances::world_bounds(&model.bounds, &data[..*len]),
+                None => model.bounds,
+            };
+            spatial_index.insert(model_renderers.len(), world_bounds);
+            let cube_renderer = ModelRenderer::new_renderer_in_background(
+                model,
+                &inputs,
+                std::borrow::Cow::Borrowed(object.shader_file),
+                instances_data,
+                instances_len,
+            );
+            model_renderers.push(cube_renderer);
+        }
+
+        let mut scene = Self {
+            context,
+            views,
+            focused_view: 0,
+            registry,
+            light_binder,
+            globals,
+            presenter,
+            quality,
+            dynamic_resolution: quality::DynamicResolution::new(&quality),
+            background_renderer,
+            panorama_renderer,
+            show_panorama: true,
+            model_renderers,
+            spatial_index,
+            instance_handles: Vec::new(),
+            videos: Vec::new(),
+            paused: false,
+            picker: None,
+            screenshot_request: None,
+            screenshot: None,
+            depth_format,
+            debug_view: DebugView::Shaded,
+            debug_palette,
+            heatmap: None,
+            plugins: Vec::new(),
+        };
+        scene.apply_render_scale();
+        scene
+    }
+
+    fn stats(&self) -> SceneStats {
+        let mut stats = SceneStats {
+            nodes: self.model_renderers.len(),
+            ..SceneStats::default()
+        };
+        for renderer in &self.model_renderers {
+            stats.meshes += renderer.model.meshes.len();
+            stats.materials += renderer.model.materials.as_ref().map_or(0, Vec::len);
+            stats.triangles += renderer
+                .model
+                .meshes
+                .iter()
+                .map(|mesh| mesh.geometry.num_elements as usize / 3)
+                .sum::<usize>();
+        }
+        stats.bounds = self.world_bounds();
+        stats
+    }
+
+    // Bounds of the models where they are drawn, at each of their instances
+    fn world_bounds(&self) -> bounds::Aabb {
+        (0..self.model_renderers.len())
+            .filter_map(|index| self.spatial_index.bounds(index))
+            .fold(bounds::Aabb::empty(), |world_bounds, bounds| {
+                world_bounds.union(&bounds)
+            })
+    }
+
+    fn view_index(&self, window_id: winit::window::WindowId) -> Option<usize> {
+        self.views
+            .iter()
+            .position(|view| view.window.id() == window_id)
+    }
+
+    fn close_view(&mut self, view: usize) {
+        let window = self.views.remove(view).window.id();
+        for plugin in &mut self.plugins {
+            plugin.close(&self.context, window);
+        }
+        if self.focused_view > view || self.focused_view >= self.views.len() {
+            self.focused_view = self.focused_view.saturating_sub(1);
+        }
+    }
+
+    fn resize(&mut self, view: usize, new_size: winit::dpi::PhysicalSize<u32>) {
+        self.views[view].resize(&self.context.device, new_size);
+        // a logical resolution keeps its aspect, it is fitted when presented
+        if self.logical_size().is_none() {
+            let view = &mut self.views[view];
+            view.camera_binder
+                .projection
+                .resize(view.config.width, view.config.height);
+        }
+        self.update_target(view);
+        let (window, size) = (self.views[view].window.id(), self.target_size(view));
+        for plugin in &mut self.plugins {
+            plugin.resize(&self.context, window, size);
+        }
+    }
+
+    fn logical_size(&self) -> Option<(u32, u32)> {
+        self.presenter
+            .as_ref()
+            .and_then(|presenter| presenter.logical_size)
+    }
+
+    // Resolution the scene of a view is drawn at
+    fn target_size(&self, view: usize) -> (u32, u32) {
+        let view = &self.views[view];
+        match view.logical_target.as_ref() {
+            Some(target) => target.size,
+            None => (view.config.width, view.config.height),
+        }
+    }
+
+    // Recreate the target of a view when the presenter draws it at another
+    // size, true when it changed
+    fn update_target(&mut self, view: usize) -> bool {
+        let view = &mut self.views[view];
+        let size = self
+            .presenter
+            .as_ref()
+            .map(|presenter| presenter.target_size((view.config.width, view.config.height)));
+        if view.logical_target.as_ref().map(|target| target.size) == size {
+            return false;
+        }
+        view.logical_target = self.presenter.as_ref().map(|presenter| {
+            presenter.create_target(&self.context.device, &view.config, self.depth_format)
+        });
+        true
+    }
+
+    // Draw the views at the render scale of the dynamic resolution. They are
+    // drawn to their surfaces when it is the whole resolution and there is no
+    // logical one.
+    fn apply_render_scale(&mut self) {
+        let scale = self.dynamic_resolution.scale;
+        if let Some(presenter) = self.presenter.as_mut() {
+            presenter.render_scale = scale;
+        } else if scale != 1.0 {
+            self.presenter = Some(present::Presenter::new(
+                &self.context.device,
+                self.context.surface_format,
+                None,
+                PresentPolicy::Stretch,
+                scale,
+            ));
+        }
+        if scale == 1.0 && self.logical_size().is_none() {
+            self.presenter = None;
+        }
+
+        for view in 0..self.views.len() {
+            if self.update_target(view) {
+                let (window, size) = (self.views[view].window.id(), self.target_size(view));
+                for plugin in &mut self.plugins {
+                    plugin.resize(&self.context, window, size);
+                }
+            }
+        }
+    }
+
+    fn set_quality(&mut self, quality: QualitySettings) {
+        self.quality = quality;
+        self.dynamic_resolution = quality::DynamicResolution::new(&quality);
+        self.apply_render_scale();
+        log::info!("drawing at {} of the resolution", quality.render_scale);
+    }
+
+    // Hand the plugins to the scene, once it can set them up
+    fn add_plugins(&mut self, plugins: Vec<Box<dyn RenderPlugin>>) {
+        let setup = PluginSetup {
+            context: &self.context,
+            format: self.views[0].config.format,
+            depth_format: self.depth_format,
+            camera_layout: &self.views[0].camera_binder.bind_group_layout,
+            light_layout: &self.light_binder.bind_group_layout,
+            globals_layout: &self.globals.bind_group_layout,
+        };
+        for mut plugin in plugins {
+            plugin.setup(&setup);
+            self.plugins.push(plugin);
+        }
+    }
+
+    // frame all the models of the scene with the camera of the focused view
+    fn frame_scene(&mut self) {
+        let scene_bounds = self.world_bounds();
+        if let Some(view) = self.views.get_mut(self.focused_view) {
+            view.camera_binder
+                .frame_aabb(&scene_bounds, camera::FRAMING_PADDING);
+        }
+    }
+
+    // pick the pixel under the cursor of a view the next time it is drawn
+    fn request_pick(&mut self, view: usize) {
+        let picker = match self.picker.as_mut() {
+            Some(picker) => picker,
+            None => return,
+        };
+        let view = &self.views[view];
+        let (mut x, mut y) = (view.cursor.x as f32, view.cursor.y as f32);
+        let (mut width, mut height) = (view.config.width, view.config.height);
+        // views with a logical resolution show their target in a part of the
+        // window, scaled
+        if let (Some(presenter), Some(target)) = (&self.presenter, &view.logical_target) {
+            let (left, top, shown_width, shown_height) = presenter.viewport((width, height));
+            width = target.size.0;
+            height = target.size.1;
+            x = (x - left) / shown_width * width as f32;
+            y = (y - top) / shown_height * height as f32;
+        }
+        if x < 0.0 || y < 0.0 || x >= width as f32 || y >= height as f32 {
+            return;
+        }
+        picker.request = Some(picker::PickRequest {
+            window: view.window.id(),
+            x: x as u32,
+            y: y as u32,
+        });
+    }
+
+    // what the pipelines of the models are built against, they draw to every
+    // view with the format of the first one
+    fn pipeline_inputs(&self) -> renderer::PipelineInputs<'_> {
+        renderer::PipelineInputs {
+            device: &self.context.device,
+            queue: &self.context.queue,
+            color_format: self.views[0].config.format,
+            depth_format: self.depth_format,
+            registry: &self.registry,
+            camera: &self.views[0].camera_binder,
+            light: &self.light_binder,
+            globals: &self.globals,
+        }
+    }
+
+    // draw the models with the debug pipelines of the view, or their own
+    // shaders for the shaded view
+    fn set_debug_view(&mut self, mut debug_view: DebugView) {
+        if debug_view == DebugView::Wireframe
+            && !self
+                .context
+                .device
+                .features()
+                .contains(wgpu::Features::POLYGON_MODE_LINE)
+        {
+            log::warn!("the adapter can't draw lines, skipping the wireframe view");
+            debug_view = debug_view.next();
+        }
+        self.debug_view = debug_view;
+        let depth_scale = self.stats().bounds.radius() * 2.0;
+        let inputs = self.pipeline_inputs();
+        let pipelines: Vec<_> = self
+            .model_renderers
+            .iter()
+            .map(|renderer| {
+                debug_view::debug_pipeline(
+                    &inputs,
+                    renderer,
+                    debug_view,
+                    self.debug_palette,
+                    depth_scale,
+                )
+            })
+            .collect();
+        for (renderer, pipeline) in self.model_renderers.iter_mut().zip(pipelines) {
+            renderer.debug_pipeline = pipeline;
+        }
+        if debug_view == DebugView::Overdraw && self.heatmap.is_none() {
+            self.heatmap = Some(debug_view::Heatmap::new(
+                &self.context.device,
+                self.views[0].config.format,
+                self.depth_format,
+                self.debug_palette,
+            ));
+        }
+        log::info!("showing the {:?} view", debug_view);
+    }
+
+    fn input(&mut self, event: &DeviceEvent) -> bool {
+        match event {
+            DeviceEvent::Key(KeyboardInput {
+                virtual_keycode: Some(VirtualKeyCode::F),
+                state: ElementState::Pressed,
+                ..
+            }) => {
+                self.frame_scene();
+                true
+            }
+            DeviceEvent::Key(KeyboardInput {
+                virtual_keycode: Some(VirtualKeyCode::V),
+                state: ElementState::Pressed,
+                ..
+            }) => {
+                self.set_debug_view(self.debug_view.next());
+                true
+            }
+            DeviceEvent::Key(KeyboardInput {
+                virtual_keycode: Some(VirtualKeyCode::H),
+                state: ElementState::Pressed,
+                ..
+            }) if self.panorama_renderer.is_some() => {
+                self.show_panorama = !self.show_panorama;
+                true
+            }
+            DeviceEvent::Key(KeyboardInput {
+                virtual_keycode: Some(VirtualKeyCode::P),
+                state: ElementState::Pressed,
+                ..
+            }) => {
+                self.screenshot_request = self
+                    .views
+                    .get(self.focused_view)
+                    .map(|view| view.window.id());
+                true
+            }
+            DeviceEvent::Key(KeyboardInput {
+                virtual_keycode: Some(VirtualKeyCode::T),
+                state: ElementState::Pressed,
+                ..
+            }) => {
+                self.paused = !self.paused;
+                true
+            }
+            DeviceEvent::Key(KeyboardInput {
+                virtual_keycode: Some(VirtualKeyCode::Q),
+                state: ElementState::Pressed,
+                ..
+            }) => {
+                let preset = QualityPreset::from_scale(self.quality.render_scale).next();
+                self.set_quality(QualitySettings {
+                    frame_budget: self.quality.frame_budget,
+                    ..preset.settings()
+                });
+                true
+            }
+            _ => match self.views.get_mut(self.focused_view) {
+                Some(view) => view.input(event),
+                None => false,
+            },
+        }
+    }
 
     fn update(&mut self, dt: std::time::Duration) {
-        // update camera position
-        self.camera_binder.update(&self.queue, dt);
+        // update camera positions
+        for view in &mut self.views {
+            let camera = &mut view.camera_binder;
+            camera.advance(dt);
+            if camera.depth_fit {
+                // the boxes beside the view, whatever their distance
+                let frustum = camera.frustum().sides();
+                let bounds = self.spatial_index.in_frustum(&frustum);
+                camera.fit_depth_range(
+                    bounds
+                        .into_iter()
+                        .filter_map(|key| self.spatial_index.bounds(key)),
+                );
+            }
+            camera.upload(&self.context.queue);
+        }
+
+        for renderer in &mut self.model_renderers {
+            renderer.poll_pipeline();
+        }
+        if self.dynamic_resolution.update(dt, &self.quality) {
+            self.apply_render_scale();
+        }
 
         // Update the light
-        self.light_binder.update(&self.queue, dt);
+        let scene_dt = match self.paused {
+            true => std::time::Duration::ZERO,
+            false => dt,
+        };
+        self.light_binder.update(&self.context.queue, scene_dt);
+        self.globals.update(scene_dt);
+        // the videos follow the time of the scene, and pause with it
+        for (index, material, video) in &mut self.videos {
+            if let Some(materials) = self.model_renderers[*index].model.materials.as_ref() {
+                let target = &materials[*material].diffuse_texture;
+                if let Err(e) = video.update(&self.context.queue, target, scene_dt) {
+                    log::error!("failed to show a video frame: {}", e);
+                }
+            }
+        }
+        for plugin in &mut self.plugins {
+            plugin.prepare(&self.context, dt);
+        }
+
+        // only the instances changed since the last frame are uploaded, the
+        // plugins' changes included
+        for (index, handle) in &self.instance_handles {
+            if let Some(instances) = handle.take_changes() {
+                let renderer = &mut self.model_renderers[*index];
+                renderer.set_instances(&self.context.device, &self.context.queue, &instances);
+                self.spatial_index.update(
+                    *index,
+                    instances::world_bounds(&renderer.model.bounds, &instances),
+                );
+            }
+        }
+    }
+
+    // Play the video in a material of a model, in place of its diffuse texture
+    fn add_video(
+        &mut self,
+        index: usize,
+        material: usize,
+        video: video::BoxedVideo,
+    ) -> anyhow::Result<()> {
+        let model = &mut self.model_renderers[index].model;
+        let (layout, materials) = match (model.material_layout.as_ref(), model.materials.as_mut()) {
+            (Some(layout), Some(materials)) => (layout, materials),
+            _ => anyhow::bail!("the object has no materials"),
+        };
+        let slot = materials
+            .get_mut(material)
+            .ok_or_else(|| anyhow::anyhow!("the object has no material {}", material))?;
+        let texture = video.create_texture(
+            &self.context.device,
+            &self.context.queue,
+            Some("video_texture"),
+        )?;
+        *slot = renderer::Material::new(&self.context.device, layout, slot.name.clone(), texture);
+        self.videos.push((index, material, video));
+        Ok(())
     }
 
-    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
-        let view = output
+    // Background and models seen through one eye of a view, shaded draws the
+    // models with their own shaders whatever the debug view
+    fn draw_eye<'b>(
+        &'b self,
+        render_pass: &mut wgpu::RenderPass<'b>,
+        bind_groups: &'b [&'b wgpu::BindGroup; 4],
+        visible: &[&'b ModelRenderer],
+        shaded: bool,
+    ) {
+        let panorama = self
+            .panorama_renderer
+            .as_ref()
+            .filter(|_| self.show_panorama);
+        if panorama.is_none() {
+            self.background_renderer.draw(render_pass, bind_groups);
+        }
+        for renderer in panorama.into_iter().chain(visible.iter().copied()) {
+            if shaded {
+                render_pass.draw_model_with_pipeline(
+                    renderer,
+                    &renderer.render_pipeline,
+                    bind_groups,
+                );
+            } else {
+                render_pass.draw_model(renderer, bind_groups);
+            }
+        }
+    }
+
+    fn render(&mut self, view: usize) -> Result<(), wgpu::SurfaceError> {
+        // the overdraw view counts fragments at the resolution of the scene
+        // target
+        if let Some(heatmap) = self
+            .heatmap
+            .as_ref()
+            .filter(|_| self.debug_view == DebugView::Overdraw)
+        {
+            let size = self.target_size(view);
+            let view = &mut self.views[view];
+            if view.overdraw_target.as_ref().map(|target| target.size) != Some(size) {
+                view.overdraw_target = Some(heatmap.create_target(&self.context.device, size));
+            }
+        }
+        let view = &self.views[view];
+        let output = view.surface.get_current_texture()?;
+        let output_view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Render Encoder"),
-            });
+        let mut encoder =
+            self.context
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Render Encoder"),
+                });
+
+        // views with a logical resolution or a render scale are drawn
+        // offscreen, then presented
+        let (target_view, depth_view, (width, height)) = match view.logical_target.as_ref() {
+            Some(target) => (&target.color_view, &target.depth_texture.view, target.size),
+            None => (
+                &output_view,
+                &view.depth_texture.view,
+                (view.config.width, view.config.height),
+            ),
+        };
+        self.globals
+            .write(&self.context.queue, (width, height), &view.camera_binder);
 
-        // create bind_groups for each model to render
-        let bind_groups = &[
-            &self.camera_binder.bind_group,
-            &self.light_binder.bind_group,
-        ];
+        // create bind_groups for each model to render. A stereo view draws the
+        // scene once per eye, side by side, with the camera of each eye.
+        let (width, height) = (width as f32, height as f32);
+        // models out of the frustum are skipped, stereo views draw everything
+        // as their eyes see a little more than the camera
+        let visible: Vec<&ModelRenderer> = match view.camera_binder.stereo {
+            Some(_) => self.model_renderers.iter().collect(),
+            None => {
+                let mut visible = self.spatial_index.in_frustum(&view.camera_binder.frustum());
+                visible.sort_unstable();
+                visible
+                    .into_iter()
+                    .map(|index| &self.model_renderers[index])
+                    .collect()
+            }
+        };
+        let eyes = match view.camera_binder.stereo.as_ref() {
+            Some(stereo) => vec![
+                (
+                    [
+                        &self.registry.empty_bind_group,
+                        stereo.bind_group(camera::Eye::Left),
+                        &self.light_binder.bind_group,
+                        &self.globals.bind_group,
+                    ],
+                    0.0,
+                    width * 0.5,
+                ),
+                (
+                    [
+                        &self.registry.empty_bind_group,
+                        stereo.bind_group(camera::Eye::Right),
+                        &self.light_binder.bind_group,
+                        &self.globals.bind_group,
+                    ],
+                    width * 0.5,
+                    width * 0.5,
+                ),
+            ],
+            None => vec![(
+                [
+                    &self.registry.empty_bind_group,
+                    &view.camera_binder.bind_group,
+                    &self.light_binder.bind_group,
+                    &self.globals.bind_group,
+                ],
+                0.0,
+                width,
+            )],
+        };
+
+        let overdraw = match (&self.heatmap, &view.overdraw_target) {
+            (Some(heatmap), Some(target)) if self.debug_view == DebugView::Overdraw => {
+                Some((heatmap, target))
+            }
+            _ => None,
+        };
+        if let Some((_, target)) = overdraw {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Overdraw Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &target.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+            for (bind_groups, x, width) in &eyes {
+                render_pass.set_viewport(*x, 0.0, *width, height, 0.0, 1.0);
+                for renderer in &visible {
+                    render_pass.draw_model(renderer, bind_groups);
+                }
+            }
+        }
 
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
@@ -198,35 +962,169 @@ impl<'a> Scene {
                 color_attachments: &[
                     // This is what [[location(0)]] in the fragment shader targets
                     wgpu::RenderPassColorAttachment {
-                        view: &view,
+                        view: target_view,
                         resolve_target: None,
                         ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Clear(wgpu::Color {
-                                r: 0.1,
-                                g: 0.2,
-                                b: 0.3,
-                                a: 1.0,
-                            }),
+                            load: wgpu::LoadOp::Clear(self.background_renderer.clear_color),
                             store: true,
                         },
                     },
                 ],
                 depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &self.depth_texture.view,
+                    view: depth_view,
                     depth_ops: Some(wgpu::Operations {
                         load: wgpu::LoadOp::Clear(1.0),
                         store: true,
                     }),
-                    stencil_ops: None,
+                    stencil_ops: texture::Texture::stencil_ops(self.depth_format),
                 }),
             });
 
-            for renderer in &self.model_renderers {
-                render_pass.draw_model(renderer, bind_groups);
+            match overdraw {
+                Some((heatmap, target)) => heatmap.draw(&mut render_pass, target),
+                None => {
+                    for (bind_groups, x, width) in &eyes {
+                        render_pass.set_viewport(*x, 0.0, *width, height, 0.0, 1.0);
+                        self.draw_eye(&mut render_pass, bind_groups, &visible, false);
+                    }
+                }
+            }
+        }
+        let plugin_target = |color, depth| PluginTarget {
+            window: view.window.id(),
+            color,
+            depth,
+            size: (width as u32, height as u32),
+            eyes: eyes
+                .iter()
+                .map(|(bind_groups, x, width)| PluginEye {
+                    bind_groups: *bind_groups,
+                    x: *x,
+                    width: *width,
+                })
+                .collect(),
+        };
+        if !self.plugins.is_empty() {
+            let target = plugin_target(target_view, depth_view);
+            for plugin in &mut self.plugins {
+                plugin.render(&self.context, &mut encoder, &target);
+            }
+        }
+        // the screenshot is drawn again in full into a target that can be
+        // copied, with the plugins drawing over it as over the scene target
+        let screenshot = self
+            .screenshot_request
+            .filter(|window| *window == view.window.id())
+            .map(|_| {
+                screenshot::Screenshot::new(
+                    &self.context.device,
+                    view.config.format,
+                    self.depth_format,
+                    (width as u32, height as u32),
+                )
+            });
+        if let Some(screenshot) = screenshot.as_ref() {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Screenshot Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &screenshot.color_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(self.background_renderer.clear_color),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &screenshot.depth_texture.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: texture::Texture::stencil_ops(self.depth_format),
+                }),
+            });
+            match overdraw {
+                Some((heatmap, target)) => heatmap.draw(&mut render_pass, target),
+                None => {
+                    for (bind_groups, x, width) in &eyes {
+                        render_pass.set_viewport(*x, 0.0, *width, height, 0.0, 1.0);
+                        self.draw_eye(&mut render_pass, bind_groups, &visible, false);
+                    }
+                }
+            }
+            drop(render_pass);
+            let target = plugin_target(&screenshot.color_view, &screenshot.depth_texture.view);
+            for plugin in &mut self.plugins {
+                plugin.render(&self.context, &mut encoder, &target);
+            }
+        }
+        if let Some(screenshot) = screenshot {
+            screenshot.copy(&mut encoder);
+            self.screenshot_request = None;
+            self.screenshot = Some(screenshot);
+        }
+        // the picked pixel is drawn again alone, at the origin of the picker.
+        // The overdraw view picks the shaded colors.
+        let pick = self
+            .picker
+            .as_ref()
+            .and_then(|picker| picker.request)
+            .filter(|request| request.window == view.window.id());
+        if let (Some(picker), Some(request)) = (self.picker.as_ref(), pick) {
+            // the dither of faded instances is offset back to the picked pixel
+            let (px, py) = (request.x as f32, request.y as f32);
+            self.globals
+                .write_offset(&self.context.queue, &picker.globals_buffer, [px, py]);
+            let eyes: Vec<_> = eyes
+                .iter()
+                .map(|(bind_groups, x, width)| {
+                    let [material, camera, light, _] = *bind_groups;
+                    (
+                        [material, camera, light, &picker.globals_bind_group],
+                        *x,
+                        *width,
+                    )
+                })
+                .collect();
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Picker Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &picker.color_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(self.background_renderer.clear_color),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &picker.depth_texture.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: texture::Texture::stencil_ops(self.depth_format),
+                }),
+            });
+            for (bind_groups, x, width) in &eyes {
+                render_pass.set_viewport(*x - px, -py, *width, height, 0.0, 1.0);
+                self.draw_eye(&mut render_pass, bind_groups, &visible, overdraw.is_some());
             }
         }
+        if pick.is_some() {
+            if let Some(picker) = self.picker.as_mut() {
+                picker.copy(&mut encoder);
+            }
+        }
+        if let (Some(presenter), Some(target)) = (&self.presenter, &view.logical_target) {
+            presenter.present(
+                &mut encoder,
+                target,
+                &output_view,
+                (view.config.width, view.config.height),
+            );
+        }
         // submit will accept anything that implements IntoIter
-        self.queue.submit(std::iter::once(encoder.finish()));
+        self.context.queue.submit(std::iter::once(encoder.finish()));
         output.present();
 
         Ok(())
@@ -241,17 +1139,78 @@ pub struct LensObject<'a> {
     pub instances: Option<(Vec<InstanceRaw>, usize)>,
 }
 
+impl<'a> LensObject<'a> {
+    // Transform of the object in the hierarchy, its position without one
+    fn world_transform(&self) -> cgmath::Matrix4<f32> {
+        self.transform
+            .unwrap_or_else(|| cgmath::Matrix4::from_translation(self.position))
+    }
+}
+
 pub struct Lens<'a> {
     // add a light
     // add a camera
     // add meshes
     lens_objects: Vec<LensObject<'a>>,
+    // windows to open, each one with its own camera
+    window_builders: Vec<WindowBuilder>,
+    // and whether the cameras only rotate at its center
+    panorama: Option<(image::DynamicImage, bool)>,
+    // the cameras orbit around the center of the scene
+    orbit: bool,
+    stereo_separation: Option<f32>,
+    logical_resolution: Option<((u32, u32), PresentPolicy)>,
+    quality: QualitySettings,
+    background: Background,
+    // sky and ground colors of the ambient light
+    ambient: Option<([f32; 3], [f32; 3])>,
+    // time step of every frame in deterministic mode
+    frame_duration: Option<std::time::Duration>,
+    input_recorder: Option<input::InputRecorder>,
+    // replaces the live device input while it lasts
+    input_playback: Option<input::InputPlayback>,
+    // button picking the color under the cursor, and who gets it
+    color_picker: Option<(MouseButton, picker::OnPick)>,
+    depth_format: Option<wgpu::TextureFormat>,
+    depth_fit: bool,
+    debug_view: DebugView,
+    debug_palette: DebugPalette,
+    plugins: Vec<Box<dyn RenderPlugin>>,
+    // instances changed while the scene runs, with the index of their object
+    instance_handles: Vec<(usize, InstanceHandle)>,
+    // videos played into a material, by object and material index
+    videos: Vec<(usize, usize, video::BoxedVideo)>,
+}
+
+impl<'a> Default for Lens<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<'a> Lens<'a> {
     pub fn new() -> Lens<'a> {
         Lens {
             lens_objects: Vec::new(),
+            window_builders: Vec::new(),
+            panorama: None,
+            orbit: false,
+            stereo_separation: None,
+            logical_resolution: None,
+            quality: QualitySettings::default(),
+            background: Background::default(),
+            ambient: None,
+            frame_duration: None,
+            input_recorder: None,
+            input_playback: None,
+            color_picker: None,
+            depth_format: None,
+            depth_fit: false,
+            debug_view: DebugView::default(),
+            debug_palette: DebugPalette::default(),
+            plugins: Vec::new(),
+            instance_handles: Vec::new(),
+            videos: Vec::new(),
         }
     }
 
@@ -259,31 +1218,272 @@ impl<'a> Lens<'a> {
         self.lens_objects.push(lens_object);
     }
 
+    // Open one more window on the scene. A single default window is opened
+    // when none is added.
+    pub fn add_window(&mut self, window_builder: WindowBuilder) {
+        self.window_builders.push(window_builder);
+    }
+
+    // Surround the scene with an equirectangular photo or video frame, and
+    // only let the cameras look around, as in a 360 viewer
+    pub fn set_panorama(&mut self, image: image::DynamicImage) {
+        self.panorama = Some((image, true));
+    }
+
+    // Surround the scene with an equirectangular image while the cameras keep
+    // moving freely, e.g. an HDRI around a model. The H key hides it.
+    pub fn set_environment(&mut self, image: image::DynamicImage) {
+        self.panorama = Some((image, false));
+    }
+
+    // Instances of an object added with some, which can be changed while the
+    // scene runs, e.g. from a plugin or another thread. None for the other
+    // objects.
+    pub fn instances(&mut self, object: usize) -> Option<InstanceHandle> {
+        let (data, len) = self.lens_objects.get(object)?.instances.as_ref()?;
+        let handle = InstanceHandle::new(data[..*len].to_vec());
+        self.instance_handles.push((object, handle.clone()));
+        Some(handle)
+    }
+
+    // Play a video in one of the materials of an object, in place of its
+    // diffuse texture, from when the scene starts
+    pub fn add_video<S: FrameSource + 'static>(
+        &mut self,
+        object: usize,
+        material: usize,
+        video: VideoTexture<S>,
+    ) {
+        let source: Box<dyn FrameSource> = Box::new(video.source);
+        self.videos
+            .push((object, material, VideoTexture::new(source)));
+    }
+
+    // Turn the cameras around the center of the scene instead of flying them,
+    // the mouse orbits and scrolling zooms. The F key frames the scene again.
+    pub fn set_orbit(&mut self, orbit: bool) {
+        self.orbit = orbit;
+    }
+
+    // Counts of the objects added so far
+    pub fn stats(&self) -> SceneStats {
+        let mut stats = SceneStats::default();
+        for lens_object in &self.lens_objects {
+            stats.add_object(&lens_object.object, &lens_object.world_transform());
+        }
+        stats
+    }
+
+    // Objects added so far and their meshes, in the order they are added
+    pub fn nodes(&self) -> impl Iterator<Item = NodeInfo<'_>> {
+        self.lens_objects.iter().flat_map(|lens_object| {
+            let transform = lens_object.world_transform();
+            let models = &lens_object.object.models;
+            let name = models.first().map_or("object", |model| model.name.as_str());
+            std::iter::once(NodeInfo {
+                name,
+                depth: 0,
+                transform,
+            })
+            .chain(models.iter().map(move |model| NodeInfo {
+                name: model.name.as_str(),
+                depth: 1,
+                transform,
+            }))
+        })
+    }
+
+    // Draw every window side by side for the left and right eyes, for
+    // stereoscopes and cardboard headsets. The separation is in scene units.
+    pub fn set_stereo(&mut self, eye_separation: f32) {
+        self.stereo_separation = Some(eye_separation);
+    }
+
+    // Render at a fixed resolution whatever the size of the windows, e.g. for
+    // pixel art, and fit the result into each window following the policy
+    pub fn set_logical_resolution(&mut self, width: u32, height: u32, policy: PresentPolicy) {
+        self.logical_resolution = Some(((width, height), policy));
+    }
+
+    // Graphics options the scene starts with, the Q key goes to the next preset
+    pub fn set_quality(&mut self, quality: QualitySettings) {
+        self.quality = quality;
+    }
+
+    // What is drawn behind the models, a panorama replaces it when set
+    pub fn set_background(&mut self, background: Background) {
+        self.background = background;
+    }
+
+    // Light the surfaces from above with the sky color and from below with the
+    // ground one, blended along their normals, as a cheap stand-in for the
+    // light bounced around. By default it follows the gradient or the sky of
+    // the background, or is a flat share of the light color.
+    pub fn set_ambient(&mut self, sky: wgpu::Color, ground: wgpu::Color) {
+        let rgb = |color: wgpu::Color| [color.r as f32, color.g as f32, color.b as f32];
+        self.ambient = Some((rgb(sky), rgb(ground)));
+    }
+
+    // Fit the near and far planes of the cameras to the models beside their
+    // views every frame, for the most depth precision in scenes of any scale
+    pub fn set_depth_fit(&mut self, fit: bool) {
+        self.depth_fit = fit;
+    }
+
+    // Draw an effect or system of another crate with the scene, plugins are
+    // set up and drawn in the order they are added
+    pub fn add_plugin<P: RenderPlugin + 'static>(&mut self, plugin: P) {
+        self.plugins.push(Box::new(plugin));
+    }
+
+    // Format of the depth targets, one of Texture::DEPTH_FORMATS, e.g.
+    // Depth24PlusStencil8 for a stencil. Formats the adapter doesn't support
+    // fall back to Texture::DEPTH_FORMAT.
+    pub fn set_depth_format(&mut self, format: wgpu::TextureFormat) {
+        self.depth_format = Some(format);
+    }
+
+    // View the scene starts with, the V key goes to the next one
+    pub fn set_debug_view(&mut self, debug_view: DebugView) {
+        self.debug_view = debug_view;
+    }
+
+    // Color map of the depth view
+    pub fn set_debug_palette(&mut self, palette: DebugPalette) {
+        self.debug_palette = palette;
+    }
+
+    // Advance every frame by the same step instead of the wall clock time, so
+    // that the animations, and the time and frame seen by the shaders, are the
+    // same from one run to the other, e.g. for golden image tests
+    pub fn set_deterministic(&mut self, frames_per_second: u32) {
+        self.frame_duration = Some(std::time::Duration::from_secs_f64(
+            1.0 / frames_per_second.max(1) as f64,
+        ));
+    }
+
+    // Write the keyboard and mouse input of each frame to a file
+    pub fn record_input<P: AsRef<std::path::Path>>(&mut self, path: P) -> std::io::Result<()> {
+        self.input_recorder = Some(input::InputRecorder::create(path)?);
+        Ok(())
+    }
+
+    // Play a recorded input file instead of the live input, which comes back
+    // once the recording is over. Used with set_deterministic, the frames
+    // shown are the same as when it was recorded.
+    pub fn play_input<P: AsRef<std::path::Path>>(&mut self, path: P) -> anyhow::Result<()> {
+        self.input_playback = Some(input::InputPlayback::open(path)?);
+        Ok(())
+    }
+
+    // Read back the color under the cursor each time the button is pressed in
+    // a window, e.g. for an eyedropper or to check the output of a shader.
+    // The color is given at the frame after the press.
+    pub fn set_color_picker<F: FnMut(PickedColor) + 'static>(
+        &mut self,
+        button: MouseButton,
+        on_pick: F,
+    ) {
+        self.color_picker = Some((button, Box::new(on_pick)));
+    }
+
     pub fn run(&mut self) {
-        env_logger::init();
+        // the application may have set up the logger already
+        let _ = env_logger::try_init();
         let mut last_render_time = std::time::Instant::now();
+        let frame_duration = self.frame_duration;
+        let mut input_recorder = self.input_recorder.take();
+        let mut input_playback = self.input_playback.take();
+        let mut color_picker = self.color_picker.take();
 
         let event_loop = EventLoop::new();
-        let window = WindowBuilder::new().build(&event_loop).unwrap();
+        if self.window_builders.is_empty() {
+            self.window_builders.push(WindowBuilder::new());
+        }
+        let windows = self
+            .window_builders
+            .drain(..)
+            .map(|window_builder| window_builder.build(&event_loop).unwrap())
+            .collect();
+        let objects = self.lens_objects.len();
         // Scene::new uses async code, so we're going to wait for it to finish
-        let mut scene = pollster::block_on(Scene::new(&window, &mut self.lens_objects));
+        let mut scene = pollster::block_on(Scene::new(
+            windows,
+            &mut self.lens_objects,
+            SceneOptions {
+                panorama: self.panorama.take(),
+                stereo_separation: self.stereo_separation,
+                logical_resolution: self.logical_resolution,
+                quality: self.quality,
+                background: std::mem::take(&mut self.background),
+                ambient: self.ambient,
+                depth_format: self.depth_format,
+                debug_palette: self.debug_palette,
+            },
+        ));
+        if self.debug_view != DebugView::Shaded {
+            scene.set_debug_view(self.debug_view);
+        }
+        // the objects are popped, so the renderers are in the reverse order
+        scene.instance_handles = self
+            .instance_handles
+            .drain(..)
+            .map(|(object, handle)| (objects - 1 - object, handle))
+            .collect();
+        for (object, material, video) in self.videos.drain(..) {
+            if object >= objects {
+                log::error!(
+                    "can't play a video on object {}, there are {}",
+                    object,
+                    objects
+                );
+            } else if let Err(e) = scene.add_video(objects - 1 - object, material, video) {
+                log::error!("can't play a video on object {}: {}", object, e);
+            }
+        }
+        let center = scene.stats().bounds.center();
+        for view in &mut scene.views {
+            view.camera_binder.depth_fit = self.depth_fit;
+            if self.orbit && !view.camera_binder.camera_controller.rotation_only {
+                view.camera_binder.orbit(center);
+            }
+        }
+        scene.add_plugins(std::mem::take(&mut self.plugins));
+        log::info!("{:?}", scene.stats());
+        if color_picker.is_some() {
+            scene.picker = Some(picker::ColorPicker::new(
+                &scene.context.device,
+                scene.context.surface_format,
+                scene.depth_format,
+                &scene.globals,
+            ));
+        }
 
         event_loop.run(move |event, _, control_flow| {
             *control_flow = ControlFlow::Poll;
             match event {
+                // live input is ignored during playback
                 Event::DeviceEvent {
                     ref event,
                     .. // We're not using device_id currently
-                } => {
+                } if input_playback.is_none() => {
                     scene.input(event);
+                    if let Some(recorder) = input_recorder.as_mut() {
+                        if let Err(e) = recorder.record(event) {
+                            log::error!("failed to record input: {}", e);
+                        }
+                    }
                 }
                 Event::WindowEvent {
                     ref event,
                     window_id,
-                } if window_id == window.id() => {
+                } => {
+                    let view = match scene.view_index(window_id) {
+                        Some(view) => view,
+                        None => return,
+                    };
                     match event {
-                        WindowEvent::CloseRequested
-                        | WindowEvent::KeyboardInput {
+                        WindowEvent::KeyboardInput {
                             input:
                                 KeyboardInput {
                                     state: ElementState::Pressed,
@@ -292,25 +1492,46 @@ impl<'a> Lens<'a> {
                                 },
                             ..
                         } => *control_flow = ControlFlow::Exit,
+                        WindowEvent::CloseRequested => {
+                            scene.close_view(view);
+                            if scene.views.is_empty() {
+                                *control_flow = ControlFlow::Exit;
+                            }
+                        }
+                        WindowEvent::CursorMoved { position, .. } => {
+                            scene.views[view].cursor = *position;
+                        }
+                        WindowEvent::MouseInput {
+                            state: ElementState::Pressed,
+                            button,
+                            ..
+                        } if color_picker.as_ref().map(|(picker_button, _)| picker_button)
+                            == Some(button) =>
+                        {
+                            scene.request_pick(view);
+                        }
+                        WindowEvent::Focused(true) => {
+                            scene.focused_view = view;
+                        }
                         WindowEvent::Resized(physical_size) => {
-                            scene.resize(*physical_size);
+                            scene.resize(view, *physical_size);
                         }
                         WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
                             // new_inner_size is &&mut so we have to dereference it twice
-                            scene.resize(**new_inner_size);
+                            scene.resize(view, **new_inner_size);
                         }
                         _ => {}
                     }
                 }
-                Event::RedrawRequested(_) => {
-                    let now = std::time::Instant::now();
-                    let dt = now - last_render_time;
-                    last_render_time = now;
-                    scene.update(dt);
-                    match scene.render() {
+                Event::RedrawRequested(window_id) => {
+                    let view = match scene.view_index(window_id) {
+                        Some(view) => view,
+                        None => return,
+                    };
+                    match scene.render(view) {
                         Ok(_) => {}
                         // Reconfigure the surface if lost
-                        Err(wgpu::SurfaceError::Lost) => scene.resize(scene.size),
+                        Err(wgpu::SurfaceError::Lost) => scene.resize(view, scene.views[view].size),
                         // The system is out of memory, we should probably quit
                         Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
                         // All other errors (Outdated, Timeout) should be resolved by the next frame
@@ -318,9 +1539,60 @@ impl<'a> Lens<'a> {
                     }
                 }
                 Event::MainEventsCleared => {
+                    // update once per frame, whatever the number of windows
+                    let now = std::time::Instant::now();
+                    let dt = frame_duration.unwrap_or(now - last_render_time);
+                    last_render_time = now;
+                    // picks drawn in the last frame
+                    if let (Some(picker), Some((_, on_pick))) =
+                        (scene.picker.as_mut(), color_picker.as_mut())
+                    {
+                        match picker.read(&scene.context.device) {
+                            Some(Ok(picked)) => on_pick(picked),
+                            Some(Err(e)) => log::error!("failed to read the picked color: {}", e),
+                            None => {}
+                        }
+                    }
+                    // screenshot drawn in the last frame, encoded and written
+                    // on the workers
+                    if let Some(screenshot) = scene.screenshot.take() {
+                        match screenshot.read(&scene.context.device) {
+                            Ok(image) => {
+                                let path = screenshot::next_path();
+                                jobs::spawn_job(move || match image.save(&path) {
+                                    Ok(()) => log::info!("saved a screenshot to {}", path.display()),
+                                    Err(e) => log::error!("failed to save {}: {}", path.display(), e),
+                                });
+                            }
+                            Err(e) => log::error!("failed to read the screenshot: {}", e),
+                        }
+                    }
+                    if let Some(playback) = input_playback.as_mut() {
+                        for event in playback.next_frame() {
+                            scene.input(&event);
+                        }
+                        if playback.is_finished() {
+                            log::info!("input playback is over");
+                            input_playback = None;
+                        }
+                    }
+                    scene.update(dt);
+                    if let Some(recorder) = input_recorder.as_mut() {
+                        recorder.next_frame();
+                    }
+
                     // RedrawRequested will only trigger once, unless we manually
                     // request it.
-                    window.request_redraw();
+                    for view in &scene.views {
+                        view.window.request_redraw();
+                    }
+                }
+                Event::LoopDestroyed => {
+                    if let Some(recorder) = input_recorder.as_mut() {
+                        if let Err(e) = recorder.flush() {
+                            log::error!("failed to record input: {}", e);
+                        }
+                    }
                 }
                 _ => {}
             }
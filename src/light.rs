@@ -2,12 +2,29 @@ use cgmath::prelude::*;
 use wgpu::util::DeviceExt;
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct LightUniform {
     pub position: [f32; 3],
     // Due to uniforms requiring 16 byte (4 float) spacing, we need to use a padding field here
     pub _padding: u32,
     pub color: [f32; 3],
+    // Pads the struct to the 16 bytes alignment of its vec3 fields in shaders
+    pub _padding2: u32,
+    // ambient light coming from above and from below, blended along the normal
+    pub sky_color: [f32; 3],
+    pub _padding3: u32,
+    pub ground_color: [f32; 3],
+    pub _padding4: u32,
+}
+
+// Share of the light color lighting the surfaces it doesn't reach, when the
+// ambient isn't given by the sky
+pub const AMBIENT_STRENGTH: f32 = 0.2;
+
+// Ambient light as flat as the light color scaled down, the same from any side
+pub fn flat_ambient(color: [f32; 3]) -> ([f32; 3], [f32; 3]) {
+    let ambient = color.map(|channel| channel * AMBIENT_STRENGTH);
+    (ambient, ambient)
 }
 
 pub struct Light {
@@ -58,6 +75,10 @@ impl Light {
     }
 
     pub fn update(&mut self, queue: &wgpu::Queue, dt: std::time::Duration) {
+        // a paused scene keeps the light still, and its buffer as it is
+        if dt.is_zero() {
+            return;
+        }
         let old_position: cgmath::Vector3<_> = self.light_uniform.position.into();
         self.light_uniform.position = (cgmath::Quaternion::from_axis_angle(
             (0.0, 1.0, 0.0).into(),
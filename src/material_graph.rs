@@ -0,0 +1,374 @@
+use crate::binding;
+use crate::globals::{DITHER_IMPORT, GLOBALS_IMPORT};
+use anyhow::*;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Node of a material graph, as returned by MaterialGraph::add. It remembers
+// its graph, so that compiling another one with it fails.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct NodeId {
+    graph: u64,
+    index: usize,
+}
+
+// Every node evaluates to a vec4, e.g. texture coordinates are (u, v, 0, 1) and
+// scalars are repeated over the four components
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MaterialNode {
+    Constant([f32; 4]),
+    TexCoords,
+    // world normal and position of the fragment
+    Normal,
+    Position,
+    // seconds since the scene started
+    Time,
+    // diffuse texture of the model material at the xy of the node
+    Texture {
+        uv: NodeId,
+    },
+    Add(NodeId, NodeId),
+    Subtract(NodeId, NodeId),
+    Multiply(NodeId, NodeId),
+    Divide(NodeId, NodeId),
+    Mix {
+        a: NodeId,
+        b: NodeId,
+        factor: NodeId,
+    },
+    // 0 facing the camera up to 1 at grazing angles, sharper with the power
+    Fresnel {
+        power: f32,
+    },
+}
+
+// Material described as nodes, each one computed from the ones added before
+// it, compiled to the WGSL of a model shader
+#[derive(Debug, Clone)]
+pub struct MaterialGraph {
+    // identity of the graph in its node ids, shared by its clones
+    id: u64,
+    nodes: Vec<MaterialNode>,
+    output: Option<NodeId>,
+    // shade the output color with the scene light, otherwise draw it as is
+    pub lit: bool,
+    // read the instance transforms, for objects with instances
+    pub instanced: bool,
+}
+
+impl Default for MaterialGraph {
+    fn default() -> Self {
+        static GRAPHS: AtomicU64 = AtomicU64::new(0);
+        Self {
+            id: GRAPHS.fetch_add(1, Ordering::Relaxed),
+            nodes: Vec::new(),
+            output: None,
+            lit: false,
+            instanced: false,
+        }
+    }
+}
+
+impl MaterialGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, node: MaterialNode) -> NodeId {
+        self.nodes.push(node);
+        NodeId {
+            graph: self.id,
+            index: self.nodes.len() - 1,
+        }
+    }
+
+    // Node whose value is the color of the fragment
+    pub fn set_output(&mut self, node: NodeId) {
+        self.output = Some(node);
+    }
+
+    // Source of a shader for LensObject::shader_file. Texture nodes need the
+    // model to have a material.
+    pub fn compile(&self) -> Result<String> {
+        let output = self.output.context("material graph has no output node")?;
+        ensure!(
+            output.graph == self.id,
+            "output node {} is from another graph",
+            output.index
+        );
+        // clones share the identity but not the nodes added afterwards
+        ensure!(
+            output.index < self.nodes.len(),
+            "output node {} is not in the graph",
+            output.index
+        );
+
+        let mut body = String::new();
+        for (index, node) in self.nodes.iter().enumerate() {
+            let input = |id: NodeId| {
+                ensure!(
+                    id.graph == self.id,
+                    "node {} reads node {} of another graph",
+                    index,
+                    id.index
+                );
+                ensure!(
+                    id.index < index,
+                    "node {} reads node {}, which isn't added before it",
+                    index,
+                    id.index
+                );
+                Ok(format!("n{}", id.index))
+            };
+            let value = match *node {
+                MaterialNode::Constant([x, y, z, w]) => {
+                    format!("vec4<f32>({:?}, {:?}, {:?}, {:?})", x, y, z, w)
+                }
+                MaterialNode::TexCoords => "vec4<f32>(in.tex_coords, 0.0, 1.0)".to_string(),
+                MaterialNode::Normal => "vec4<f32>(normalize(in.world_normal), 0.0)".to_string(),
+                MaterialNode::Position => "vec4<f32>(in.world_position, 1.0)".to_string(),
+                MaterialNode::Time => "vec4<f32>(globals.time)".to_string(),
+                MaterialNode::Texture { uv } => {
+                    format!("textureSample(t_diffuse, s_diffuse, {}.xy)", input(uv)?)
+                }
+                MaterialNode::Add(a, b) => format!("{} + {}", input(a)?, input(b)?),
+                MaterialNode::Subtract(a, b) => format!("{} - {}", input(a)?, input(b)?),
+                MaterialNode::Multiply(a, b) => format!("{} * {}", input(a)?, input(b)?),
+                MaterialNode::Divide(a, b) => format!("{} / {}", input(a)?, input(b)?),
+                MaterialNode::Mix { a, b, factor } => {
+                    format!("mix({}, {}, {})", input(a)?, input(b)?, input(factor)?)
+                }
+                MaterialNode::Fresnel { power } => format!(
+                    "vec4<f32>(pow(1.0 - max(dot(normalize(in.world_normal), \
+                     normalize(camera.view_position.xyz - in.world_position)), 0.0), {:?}))",
+                    power
+                ),
+            };
+            writeln!(body, "    let n{} = {};", index, value)?;
+        }
+
+        let textured = self
+            .nodes
+            .iter()
+            .any(|node| matches!(node, MaterialNode::Texture { .. }));
+        let mut source = String::new();
+        // the material, camera, light and globals groups are the canonical ones
+        if textured {
+            writeln!(
+                source,
+                "[[group({0}), binding(0)]]
+var t_diffuse: texture_2d<f32>;
+[[group({0}), binding(1)]]
+var s_diffuse: sampler;
+",
+                binding::MATERIAL_GROUP
+            )?;
+        }
+        writeln!(
+            source,
+            "[[block]]
+struct Camera {{
+    view_position: vec4<f32>;
+    view_proj: mat4x4<f32>;
+}};
+[[group({}), binding(0)]]
+var<uniform> camera: Camera;
+
+[[block]]
+struct Light {{
+    position: vec3<f32>;
+    color: vec3<f32>;
+    sky_color: vec3<f32>;
+    ground_color: vec3<f32>;
+}};
+[[group({}), binding(0)]]
+var<uniform> light: Light;
+
+{}
+{}
+",
+            binding::CAMERA_GROUP,
+            binding::LIGHT_GROUP,
+            GLOBALS_IMPORT,
+            DITHER_IMPORT
+        )?;
+        source.push_str(VERTEX_IO);
+        source.push_str(if self.instanced {
+            VERTEX_INSTANCED
+        } else {
+            VERTEX
+        });
+
+        let color = if self.lit {
+            format!(
+                "    let normal = normalize(in.world_normal);
+    let ambient = mix(light.ground_color, light.sky_color, normal.y * 0.5 + 0.5);
+    let light_dir = normalize(light.position - in.world_position);
+    let diffuse_strength = max(dot(normal, light_dir), 0.0);
+    let color = n{0};
+    return vec4<f32>((ambient + diffuse_strength * light.color) * color.xyz, color.a);",
+                output.index
+            )
+        } else {
+            format!("    return n{};", output.index)
+        };
+        write!(
+            source,
+            "
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {{
+{}    // after the texture samples, which need every pixel of their quads
+    if (dither_fade(in.clip_position.xy, in.fade)) {{
+        discard;
+    }}
+{}
+}}
+",
+            body, color
+        )?;
+        Ok(source)
+    }
+}
+
+pub(crate) const VERTEX_IO: &str = "struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] tex_coords: vec2<f32>;
+    [[location(2)]] normal: vec3<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+    [[location(1)]] world_normal: vec3<f32>;
+    [[location(2)]] world_position: vec3<f32>;
+    [[location(3)]] fade: f32;
+};
+";
+
+pub(crate) const VERTEX: &str = "
+[[stage(vertex)]]
+fn vs_main(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.world_normal = model.normal;
+    out.world_position = model.position;
+    out.clip_position = camera.view_proj * vec4<f32>(model.position, 1.0);
+    out.fade = 0.0;
+    return out;
+}
+";
+
+// transforms and fade of InstanceRaw, at locations 5 to 11 and 13
+pub(crate) const VERTEX_INSTANCED: &str = "
+struct InstanceInput {
+    [[location(5)]] model_matrix_0: vec4<f32>;
+    [[location(6)]] model_matrix_1: vec4<f32>;
+    [[location(7)]] model_matrix_2: vec4<f32>;
+    [[location(8)]] model_matrix_3: vec4<f32>;
+    [[location(9)]] normal_matrix_0: vec3<f32>;
+    [[location(10)]] normal_matrix_1: vec3<f32>;
+    [[location(11)]] normal_matrix_2: vec3<f32>;
+    [[location(13)]] fade: f32;
+};
+
+[[stage(vertex)]]
+fn vs_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    let normal_matrix = mat3x3<f32>(
+        instance.normal_matrix_0,
+        instance.normal_matrix_1,
+        instance.normal_matrix_2,
+    );
+    let world_position = model_matrix * vec4<f32>(model.position, 1.0);
+
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.world_normal = normal_matrix * model.normal;
+    out.world_position = world_position.xyz;
+    out.clip_position = camera.view_proj * world_position;
+    out.fade = instance.fade;
+    return out;
+}
+";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::globals::tests::validate_wgsl;
+
+    // a graph with a node of every kind, each one read by the output
+    fn every_node() -> MaterialGraph {
+        let mut graph = MaterialGraph::new();
+        let constant = graph.add(MaterialNode::Constant([0.2, 0.6, 1.0, 1.0]));
+        let uv = graph.add(MaterialNode::TexCoords);
+        let normal = graph.add(MaterialNode::Normal);
+        let position = graph.add(MaterialNode::Position);
+        let time = graph.add(MaterialNode::Time);
+        let texture = graph.add(MaterialNode::Texture { uv });
+        let sum = graph.add(MaterialNode::Add(texture, normal));
+        let difference = graph.add(MaterialNode::Subtract(sum, position));
+        let product = graph.add(MaterialNode::Multiply(difference, time));
+        let quotient = graph.add(MaterialNode::Divide(product, constant));
+        let rim = graph.add(MaterialNode::Fresnel { power: 3.0 });
+        let color = graph.add(MaterialNode::Mix {
+            a: quotient,
+            b: constant,
+            factor: rim,
+        });
+        graph.set_output(color);
+        graph
+    }
+
+    #[test]
+    fn every_node_compiles() {
+        for (lit, instanced) in [(false, false), (true, false), (false, true), (true, true)] {
+            let graph = MaterialGraph {
+                lit,
+                instanced,
+                ..every_node()
+            };
+            validate_wgsl(&graph.compile().unwrap());
+        }
+    }
+
+    #[test]
+    fn untextured_graph_has_no_material() {
+        let mut graph = MaterialGraph::new();
+        let normal = graph.add(MaterialNode::Normal);
+        graph.set_output(normal);
+        let source = graph.compile().unwrap();
+        assert!(!source.contains("t_diffuse"));
+        validate_wgsl(&source);
+    }
+
+    #[test]
+    fn invalid_graphs() {
+        assert!(MaterialGraph::new().compile().is_err());
+
+        // nodes of another graph, as the output or as an input
+        let other = every_node();
+        let mut graph = MaterialGraph::new();
+        graph.set_output(other.output.unwrap());
+        assert!(graph.compile().is_err());
+        let time = graph.add(MaterialNode::Time);
+        let sum = graph.add(MaterialNode::Add(time, other.output.unwrap()));
+        graph.set_output(sum);
+        let error = graph.compile().unwrap_err().to_string();
+        assert!(error.contains("another graph"), "{}", error);
+
+        // a clone shares the ids, but not the nodes added after it
+        let mut graph = MaterialGraph::new();
+        let time = graph.add(MaterialNode::Time);
+        let mut clone = graph.clone();
+        clone.set_output(time);
+        assert!(clone.compile().is_ok());
+        let added = graph.add(MaterialNode::Normal);
+        clone.set_output(added);
+        assert!(clone.compile().is_err());
+    }
+}
@@ -1,9 +1,30 @@
+use crate::{jobs, validation};
+use cgmath::prelude::*;
+use cgmath::{Point3, Vector3};
+use std::collections::HashMap;
 use std::path::Path;
 use tobj::*;
 
+// Counts of what the loader removed because it can't be rendered correctly
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct SanitizeReport {
+    // zero area triangles, and triangles using a removed vertex
+    pub removed_triangles: usize,
+    // vertices with a NaN or infinite position
+    pub removed_vertices: usize,
+}
+
+// Counts of what the winding repair changed
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct WindingReport {
+    pub flipped_triangles: usize,
+    pub flipped_normals: usize,
+}
+
 pub struct Object {
     pub models: Vec<Model>,
     pub textures: Option<Vec<(image::DynamicImage, String, String)>>,
+    pub sanitize_report: SanitizeReport,
 }
 
 impl Object {
@@ -32,9 +53,487 @@ impl Object {
             textures.push((img, diffuse_path, name));
         }
 
-        Object {
+        let mut object = Object {
             models: obj_models,
             textures: Some(textures),
+            sanitize_report: SanitizeReport::default(),
+        };
+        object.sanitize_report = object.sanitize();
+        if object.sanitize_report != SanitizeReport::default() {
+            log::warn!(
+                "{:?}: removed {} triangles and {} vertices that can't be rendered",
+                path.as_ref(),
+                object.sanitize_report.removed_triangles,
+                object.sanitize_report.removed_vertices
+            );
+        }
+
+        object
+    }
+
+    // Load several files at once, decoding them on the worker threads
+    pub fn load_all<P: AsRef<Path> + Send>(paths: Vec<P>) -> Vec<Object> {
+        jobs::map_jobs(paths, Object::load_from)
+    }
+
+    // Drop the degenerate triangles and non finite vertices of every mesh
+    pub fn sanitize(&mut self) -> SanitizeReport {
+        let mut report = SanitizeReport::default();
+        for model in &mut self.models {
+            sanitize_mesh(&mut model.mesh, &mut report);
+        }
+        report
+    }
+
+    // Wind every triangle counter clockwise seen from outside, and turn the
+    // vertex normals to the side of the faces around them
+    pub fn repair_winding(&mut self) -> WindingReport {
+        let mut report = WindingReport::default();
+        for model in &mut self.models {
+            repair_mesh_winding(&mut model.mesh, &mut report);
+        }
+        report
+    }
+}
+
+fn sanitize_mesh(mesh: &mut Mesh, report: &mut SanitizeReport) {
+    let vertex_count = mesh.positions.len() / 3;
+    let position = |i: usize| {
+        Point3::new(
+            mesh.positions[i * 3],
+            mesh.positions[i * 3 + 1],
+            mesh.positions[i * 3 + 2],
+        )
+    };
+    let valid_vertices: Vec<bool> = (0..vertex_count)
+        .map(|i| {
+            mesh.positions[i * 3..i * 3 + 3]
+                .iter()
+                .all(|c| c.is_finite())
+        })
+        .collect();
+
+    let mut indices = Vec::with_capacity(mesh.indices.len());
+    for triangle in mesh.indices.chunks_exact(3) {
+        let valid = triangle
+            .iter()
+            .all(|&i| valid_vertices.get(i as usize) == Some(&true))
+            && !validation::is_degenerate_triangle(
+                position(triangle[0] as usize),
+                position(triangle[1] as usize),
+                position(triangle[2] as usize),
+            );
+        if valid {
+            indices.extend_from_slice(triangle);
+        } else {
+            report.removed_triangles += 1;
+        }
+    }
+
+    let removed_vertices = valid_vertices.iter().filter(|valid| !**valid).count();
+    if removed_vertices > 0 {
+        // shift the indices down to the remaining vertices
+        let mut remap = Vec::with_capacity(vertex_count);
+        let mut next_index = 0;
+        for valid in &valid_vertices {
+            remap.push(next_index);
+            if *valid {
+                next_index += 1;
+            }
+        }
+        for index in indices.iter_mut() {
+            *index = remap[*index as usize];
+        }
+
+        // vertex attributes are laid out per vertex, as loaded with single_index
+        let retain_valid = |attribute: &mut Vec<f32>, size: usize| {
+            if attribute.len() == vertex_count * size {
+                let mut i = 0;
+                attribute.retain(|_| {
+                    i += 1;
+                    valid_vertices[(i - 1) / size]
+                });
+            }
+        };
+        retain_valid(&mut mesh.positions, 3);
+        retain_valid(&mut mesh.normals, 3);
+        retain_valid(&mut mesh.texcoords, 2);
+        retain_valid(&mut mesh.vertex_color, 3);
+        report.removed_vertices += removed_vertices;
+    }
+
+    mesh.indices = indices;
+}
+
+// Read the 3 component vector of vertex i from a per vertex attribute
+fn vector_at(attribute: &[f32], i: u32) -> Vector3<f32> {
+    let i = i as usize;
+    Vector3::new(attribute[i * 3], attribute[i * 3 + 1], attribute[i * 3 + 2])
+}
+
+// Normal of triangle t as indexed, scaled by twice its area
+fn face_normal(mesh: &Mesh, t: usize) -> Vector3<f32> {
+    let a = vector_at(&mesh.positions, mesh.indices[t * 3]);
+    let b = vector_at(&mesh.positions, mesh.indices[t * 3 + 1]);
+    let c = vector_at(&mesh.positions, mesh.indices[t * 3 + 2]);
+    (b - a).cross(c - a)
+}
+
+fn repair_mesh_winding(mesh: &mut Mesh, report: &mut WindingReport) {
+    let vertex_count = mesh.positions.len() / 3;
+    let triangle_count = mesh.indices.len() / 3;
+    let has_normals = mesh.normals.len() == vertex_count * 3;
+
+    // vertices are split along uv and normal seams: weld them back by position
+    // so that adjacency goes across the seams
+    let mut welded_ids = HashMap::new();
+    let welded: Vec<usize> = (0..vertex_count)
+        .map(|i| {
+            let key = [
+                mesh.positions[i * 3].to_bits(),
+                mesh.positions[i * 3 + 1].to_bits(),
+                mesh.positions[i * 3 + 2].to_bits(),
+            ];
+            let next_id = welded_ids.len();
+            *welded_ids.entry(key).or_insert(next_id)
+        })
+        .collect();
+
+    // triangles using each undirected edge, with the direction they walk it in
+    let mut edges: HashMap<(usize, usize), Vec<(usize, bool)>> = HashMap::new();
+    for (t, triangle) in mesh.indices.chunks_exact(3).enumerate() {
+        for k in 0..3 {
+            let (u, v) = (
+                welded[triangle[k] as usize],
+                welded[triangle[(k + 1) % 3] as usize],
+            );
+            if u != v {
+                edges
+                    .entry((u.min(v), u.max(v)))
+                    .or_default()
+                    .push((t, u < v));
+            }
+        }
+    }
+
+    // neighbours through edges shared by exactly two triangles, and whether both
+    // walk the edge the same way, meaning one of them is wound the wrong way
+    let mut neighbours = vec![Vec::new(); triangle_count];
+    let mut on_boundary = vec![false; triangle_count];
+    for sharing in edges.values() {
+        if let [(a, a_direction), (b, b_direction)] = sharing[..] {
+            let same_direction = a_direction == b_direction;
+            neighbours[a].push((b, same_direction));
+            neighbours[b].push((a, same_direction));
+        } else {
+            sharing.iter().for_each(|(t, _)| on_boundary[*t] = true);
+        }
+    }
+
+    let mut flipped = vec![false; triangle_count];
+    let mut visited = vec![false; triangle_count];
+    for seed in 0..triangle_count {
+        if visited[seed] {
+            continue;
+        }
+
+        // walk the connected component, winding each triangle like its neighbours
+        visited[seed] = true;
+        let mut component = vec![seed];
+        let mut next = 0;
+        while next < component.len() {
+            let t = component[next];
+            next += 1;
+            for &(neighbour, same_direction) in &neighbours[t] {
+                if !visited[neighbour] {
+                    visited[neighbour] = true;
+                    flipped[neighbour] = flipped[t] ^ same_direction;
+                    component.push(neighbour);
+                }
+            }
         }
+
+        // of the two consistent windings, keep the one changing less triangles
+        let flip_count = component.iter().filter(|t| flipped[**t]).count();
+        if flip_count * 2 > component.len() {
+            component.iter().for_each(|t| flipped[*t] = !flipped[*t]);
+        }
+
+        let oriented_normal = |t: usize| {
+            if flipped[t] {
+                -face_normal(mesh, t)
+            } else {
+                face_normal(mesh, t)
+            }
+        };
+        let inside_out = if component.iter().all(|t| !on_boundary[*t]) {
+            // a closed surface has a negative signed volume when it is inside out
+            let volume: f32 = component
+                .iter()
+                .map(|t| vector_at(&mesh.positions, mesh.indices[t * 3]).dot(oriented_normal(*t)))
+                .sum();
+            volume < 0.0
+        } else if has_normals {
+            // an open surface has no volume: trust the majority of the loaded normals
+            let disagreeing = component
+                .iter()
+                .filter(|t| {
+                    let normal: Vector3<f32> = mesh.indices[**t * 3..**t * 3 + 3]
+                        .iter()
+                        .map(|i| vector_at(&mesh.normals, *i))
+                        .sum();
+                    oriented_normal(**t).dot(normal) < 0.0
+                })
+                .count();
+            disagreeing * 2 > component.len()
+        } else {
+            false
+        };
+        if inside_out {
+            component.iter().for_each(|t| flipped[*t] = !flipped[*t]);
+        }
+    }
+
+    for (t, flip) in flipped.iter().enumerate() {
+        if *flip {
+            mesh.indices.swap(t * 3 + 1, t * 3 + 2);
+            report.flipped_triangles += 1;
+        }
+    }
+
+    if has_normals {
+        // sum the normals of the faces around each welded vertex
+        let mut face_normals = vec![Vector3::zero(); welded_ids.len()];
+        for t in 0..triangle_count {
+            let normal = face_normal(mesh, t);
+            for i in &mesh.indices[t * 3..t * 3 + 3] {
+                face_normals[welded[*i as usize]] += normal;
+            }
+        }
+        for i in 0..vertex_count {
+            if vector_at(&mesh.normals, i as u32).dot(face_normals[welded[i]]) < 0.0 {
+                mesh.normals[i * 3..i * 3 + 3]
+                    .iter_mut()
+                    .for_each(|n| *n = -*n);
+                report.flipped_normals += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mesh(positions: &[[f32; 3]], indices: &[u32]) -> Mesh {
+        Mesh {
+            positions: positions.iter().flatten().copied().collect(),
+            indices: indices.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    fn sanitized(mesh: &mut Mesh) -> SanitizeReport {
+        let mut report = SanitizeReport::default();
+        sanitize_mesh(mesh, &mut report);
+        report
+    }
+
+    #[test]
+    fn sanitize_drops_out_of_range_indices() {
+        let mut mesh = mesh(
+            &[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+            &[0, 1, 2, 0, 1, 5],
+        );
+        let report = sanitized(&mut mesh);
+
+        assert_eq!(mesh.indices, [0, 1, 2]);
+        assert_eq!(
+            report,
+            SanitizeReport {
+                removed_triangles: 1,
+                removed_vertices: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn sanitize_drops_degenerate_and_nan_triangles() {
+        let mut mesh = mesh(
+            &[
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0],
+                // on the line through the first two
+                [2.0, 0.0, 0.0],
+                [f32::NAN, 0.0, 0.0],
+                [1.0, 1.0, 0.0],
+            ],
+            &[0, 1, 2, 0, 1, 3, 1, 4, 2, 1, 5, 2],
+        );
+        mesh.normals = [0.0, 0.0, 1.0].repeat(6);
+        let report = sanitized(&mut mesh);
+
+        // the last vertex moved down to the place of the NaN one
+        assert_eq!(mesh.indices, [0, 1, 2, 1, 4, 2]);
+        assert_eq!(&mesh.positions[12..], [1.0, 1.0, 0.0]);
+        assert_eq!(mesh.normals.len(), 15);
+        assert_eq!(
+            report,
+            SanitizeReport {
+                removed_triangles: 2,
+                removed_vertices: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn sanitize_keeps_finite_unused_vertices() {
+        let mut mesh = mesh(
+            &[
+                [f32::INFINITY, 0.0, 0.0],
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0],
+                [5.0, 5.0, 5.0],
+            ],
+            &[1, 2, 3],
+        );
+        let report = sanitized(&mut mesh);
+
+        assert_eq!(mesh.indices, [0, 1, 2]);
+        assert_eq!(mesh.positions.len(), 12);
+        assert_eq!(&mesh.positions[9..], [5.0, 5.0, 5.0]);
+        assert_eq!(
+            report,
+            SanitizeReport {
+                removed_triangles: 0,
+                removed_vertices: 1,
+            }
+        );
+    }
+
+    fn repaired(mesh: &mut Mesh) -> WindingReport {
+        let mut report = WindingReport::default();
+        repair_mesh_winding(mesh, &mut report);
+        report
+    }
+
+    // cube from -1 to 1, vertex i at -1 or 1 along x, y and z from its bits,
+    // wound counter clockwise seen from outside
+    fn cube() -> Mesh {
+        let positions: Vec<[f32; 3]> = (0..8)
+            .map(|i| [0, 1, 2].map(|bit| if i >> bit & 1 == 1 { 1.0 } else { -1.0 }))
+            .collect();
+        #[rustfmt::skip]
+        let indices = [
+            0, 2, 3, 0, 3, 1,
+            4, 5, 7, 4, 7, 6,
+            0, 4, 6, 0, 6, 2,
+            1, 3, 7, 1, 7, 5,
+            0, 1, 5, 0, 5, 4,
+            2, 6, 7, 2, 7, 3,
+        ];
+        mesh(&positions, &indices)
+    }
+
+    fn flip(mesh: &mut Mesh, triangles: &[usize]) {
+        triangles
+            .iter()
+            .for_each(|t| mesh.indices.swap(t * 3 + 1, t * 3 + 2));
+    }
+
+    #[test]
+    fn repair_winding_of_closed_cube() {
+        let expected = cube().indices;
+
+        let mut mesh = cube();
+        flip(&mut mesh, &[1, 6, 11]);
+        assert_eq!(
+            repaired(&mut mesh),
+            WindingReport {
+                flipped_triangles: 3,
+                flipped_normals: 0,
+            }
+        );
+        assert_eq!(mesh.indices, expected);
+
+        // consistent but inside out, and normals pointing inward
+        let mut mesh = cube();
+        flip(&mut mesh, &(0..12).collect::<Vec<_>>());
+        mesh.normals = mesh.positions.iter().map(|p| -p).collect();
+        assert_eq!(
+            repaired(&mut mesh),
+            WindingReport {
+                flipped_triangles: 12,
+                flipped_normals: 8,
+            }
+        );
+        assert_eq!(mesh.indices, expected);
+        assert_eq!(mesh.normals, cube().positions);
+    }
+
+    #[test]
+    fn repair_winding_of_open_strip() {
+        // two quads in the z = 0 plane, wound counter clockwise seen from +z
+        let positions = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [2.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [2.0, 1.0, 0.0],
+        ];
+        let expected = [0, 1, 4, 0, 4, 3, 1, 2, 5, 1, 5, 4];
+
+        // the majority of the normals face +z, the one of vertex 2 doesn't
+        let mut strip = mesh(&positions, &expected);
+        flip(&mut strip, &[1]);
+        strip.normals = [0.0, 0.0, 1.0].repeat(6);
+        strip.normals[2 * 3 + 2] = -1.0;
+        assert_eq!(
+            repaired(&mut strip),
+            WindingReport {
+                flipped_triangles: 1,
+                flipped_normals: 1,
+            }
+        );
+        assert_eq!(strip.indices, expected);
+        assert_eq!(strip.normals, [0.0, 0.0, 1.0].repeat(6));
+
+        // normals facing -z turn the whole strip over
+        let mut strip = mesh(&positions, &expected);
+        strip.normals = [0.0, 0.0, -1.0].repeat(6);
+        assert_eq!(
+            repaired(&mut strip),
+            WindingReport {
+                flipped_triangles: 4,
+                flipped_normals: 0,
+            }
+        );
+        assert_eq!(strip.indices, [0, 4, 1, 0, 3, 4, 1, 5, 2, 1, 4, 5]);
+    }
+
+    #[test]
+    fn repair_winding_across_non_manifold_edges() {
+        let positions = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.5, 1.0, 0.0],
+            [0.5, 0.0, 1.0],
+            [0.5, 0.0, -1.0],
+            [1.5, 1.0, 0.0],
+        ];
+        // three fins around the edge from 0 to 1, which doesn't connect them,
+        // and a triangle wound against the first one through the edge 1 to 2
+        let mut mesh = mesh(&positions, &[0, 1, 2, 1, 0, 3, 0, 1, 4, 1, 2, 5]);
+        assert_eq!(
+            repaired(&mut mesh),
+            WindingReport {
+                flipped_triangles: 1,
+                flipped_normals: 0,
+            }
+        );
+        assert_eq!(mesh.indices, [0, 1, 2, 1, 0, 3, 0, 1, 4, 1, 5, 2]);
     }
 }
@@ -0,0 +1,62 @@
+use crate::object::{Object, SanitizeReport};
+use crate::renderer;
+
+const STACKS: u32 = 32;
+const SLICES: u32 = 64;
+
+// Unit sphere facing inward, for the shaders drawing around the camera
+pub fn inward_sphere() -> tobj::Mesh {
+    let mut mesh = tobj::Mesh::default();
+    for i in 0..=STACKS {
+        let (polar_sin, polar_cos) = (std::f32::consts::PI * i as f32 / STACKS as f32).sin_cos();
+        for j in 0..=SLICES {
+            let (azimuth_sin, azimuth_cos) =
+                (std::f32::consts::TAU * j as f32 / SLICES as f32).sin_cos();
+            let position = [polar_sin * azimuth_cos, polar_cos, polar_sin * azimuth_sin];
+            mesh.positions.extend_from_slice(&position);
+            mesh.normals
+                .extend_from_slice(&[-position[0], -position[1], -position[2]]);
+            mesh.texcoords
+                .extend_from_slice(&[j as f32 / SLICES as f32, i as f32 / STACKS as f32]);
+        }
+    }
+
+    // two triangles per quad, counter clockwise seen from the center
+    let row = SLICES + 1;
+    for i in 0..STACKS {
+        for j in 0..SLICES {
+            let (a, b) = (i * row + j, i * row + j + 1);
+            let (c, d) = (a + row, b + row);
+            mesh.indices.extend_from_slice(&[a, c, b, b, c, d]);
+        }
+    }
+    mesh
+}
+
+// Inward sphere textured with an equirectangular image. The panorama shader
+// centers it on the camera, so only its directions matter.
+pub fn panorama_sphere(image: image::DynamicImage) -> Object {
+    let mut mesh = inward_sphere();
+    mesh.material_id = Some(0);
+
+    Object {
+        models: vec![tobj::Model::new(mesh, "panorama".into())],
+        textures: Some(vec![(image, "panorama".into(), "panorama".into())]),
+        sanitize_report: SanitizeReport::default(),
+    }
+}
+
+// Renderer drawing the panorama behind all the models of the scene
+pub fn panorama_renderer(
+    image: image::DynamicImage,
+    inputs: &renderer::PipelineInputs,
+) -> anyhow::Result<renderer::ModelRenderer> {
+    let model = renderer::Model::load(inputs.device, inputs.queue, panorama_sphere(image))?;
+    Ok(renderer::ModelRenderer::new_renderer(
+        model,
+        inputs,
+        std::borrow::Cow::Borrowed(include_str!("shader/panorama.wgsl")),
+        None,
+        None,
+    ))
+}
@@ -0,0 +1,155 @@
+use crate::{globals, texture};
+use anyhow::*;
+use winit::window::WindowId;
+
+// Color of the pixel under the cursor when the picker button was pressed. The
+// pixel is drawn again without the plugins, so what they draw over the scene
+// isn't picked.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PickedColor {
+    pub window: WindowId,
+    // pixel of the rendered image, in the logical resolution when there is one
+    pub x: u32,
+    pub y: u32,
+    // rgba as shown on screen, sRGB encoded on sRGB surfaces
+    pub color: [u8; 4],
+}
+
+// Called with each picked color
+pub(crate) type OnPick = Box<dyn FnMut(PickedColor)>;
+
+// Pixel to pick from a view, and the window it belongs to
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct PickRequest {
+    pub window: WindowId,
+    pub x: u32,
+    pub y: u32,
+}
+
+// A single pixel target the scene is drawn into, the viewport moved so that
+// the picked pixel lands on it, then copied to a buffer read at the next frame
+pub(crate) struct ColorPicker {
+    format: wgpu::TextureFormat,
+    color_texture: wgpu::Texture,
+    pub color_view: wgpu::TextureView,
+    pub depth_texture: texture::Texture,
+    // globals offset by the picked pixel, so that faded instances leave out
+    // the same pixels as in the view
+    pub globals_buffer: wgpu::Buffer,
+    pub globals_bind_group: wgpu::BindGroup,
+    // a whole row of copy alignment for the single texel
+    buffer: wgpu::Buffer,
+    // waiting for the view to be drawn
+    pub request: Option<PickRequest>,
+    // copied in the last frame
+    in_flight: Option<PickRequest>,
+}
+
+impl ColorPicker {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        depth_format: wgpu::TextureFormat,
+        globals: &globals::Globals,
+    ) -> Self {
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format,
+            width: 1,
+            height: 1,
+            present_mode: wgpu::PresentMode::Fifo,
+        };
+        let color_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Picker Texture"),
+            size: wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: config.usage,
+        });
+        let color_view = color_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth_texture = texture::Texture::create_depth_texture_with_format(
+            device,
+            &config,
+            depth_format,
+            "picker_depth_texture",
+        );
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Picker Buffer"),
+            size: wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let (globals_buffer, globals_bind_group) = globals.create_offset_group(device);
+
+        Self {
+            format,
+            color_texture,
+            color_view,
+            depth_texture,
+            globals_buffer,
+            globals_bind_group,
+            buffer,
+            request: None,
+            in_flight: None,
+        }
+    }
+
+    // Copy the drawn pixel of the request to the buffer
+    pub fn copy(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.color_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &self.buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.in_flight = self.request.take();
+    }
+
+    // Color copied in the last frame, its frame is done by now or close to it
+    pub fn read(&mut self, device: &wgpu::Device) -> Option<Result<PickedColor>> {
+        let request = self.in_flight.take()?;
+        let buffer_slice = self.buffer.slice(..);
+        let mapping = buffer_slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        if let Err(e) = pollster::block_on(mapping) {
+            return Some(Err(e.into()));
+        }
+        let mut color = [0; 4];
+        color.copy_from_slice(&buffer_slice.get_mapped_range()[..4]);
+        self.buffer.unmap();
+        if matches!(
+            self.format,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        ) {
+            color.swap(0, 2);
+        }
+
+        Some(Ok(PickedColor {
+            window: request.window,
+            x: request.x,
+            y: request.y,
+            color,
+        }))
+    }
+}
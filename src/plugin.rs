@@ -0,0 +1,64 @@
+use crate::context::GpuContext;
+use std::time::Duration;
+use winit::window::WindowId;
+
+// What plugins get once the scene is created, to build their pipelines
+pub struct PluginSetup<'a> {
+    pub context: &'a GpuContext,
+    // formats of the scene targets of every view
+    pub format: wgpu::TextureFormat,
+    pub depth_format: wgpu::TextureFormat,
+    // layouts of the scene groups, at CAMERA_GROUP, LIGHT_GROUP and
+    // GLOBALS_GROUP. MATERIAL_GROUP is free for the plugin's own resources.
+    pub camera_layout: &'a wgpu::BindGroupLayout,
+    pub light_layout: &'a wgpu::BindGroupLayout,
+    pub globals_layout: &'a wgpu::BindGroupLayout,
+}
+
+// One eye of a view, the whole view when it isn't stereo
+pub struct PluginEye<'a> {
+    // scene groups at their canonical indices, an empty group at MATERIAL_GROUP
+    pub bind_groups: [&'a wgpu::BindGroup; 4],
+    // part of the target the eye is drawn to, in pixels
+    pub x: f32,
+    pub width: f32,
+}
+
+// Scene target of a view, once its models are drawn
+pub struct PluginTarget<'a> {
+    // window of the view, which keeps it while the other views open and close
+    pub window: WindowId,
+    pub color: &'a wgpu::TextureView,
+    pub depth: &'a wgpu::TextureView,
+    pub size: (u32, u32),
+    pub eyes: Vec<PluginEye<'a>>,
+}
+
+// An effect or system drawn with the scene without forking lens, e.g. an
+// outline pass. Every hook does nothing by default.
+pub trait RenderPlugin {
+    // Called once, when the scene is created
+    fn setup(&mut self, _setup: &PluginSetup) {}
+
+    // Called every frame before the views are drawn, e.g. to upload buffers
+    fn prepare(&mut self, _context: &GpuContext, _dt: Duration) {}
+
+    // Record passes drawing over the scene target of a view, after its models
+    // and before it is presented. It is called again over the target of a
+    // screenshot of the view, so that screenshots show what the plugins draw.
+    fn render(
+        &mut self,
+        _context: &GpuContext,
+        _encoder: &mut wgpu::CommandEncoder,
+        _target: &PluginTarget,
+    ) {
+    }
+
+    // Called when the scene target of a view is resized, or recreated at the
+    // same size when there is a logical resolution
+    fn resize(&mut self, _context: &GpuContext, _window: WindowId, _size: (u32, u32)) {}
+
+    // Called when the window of a view is closed, e.g. to drop what the plugin
+    // keeps for it
+    fn close(&mut self, _context: &GpuContext, _window: WindowId) {}
+}
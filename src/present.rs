@@ -0,0 +1,311 @@
+use crate::{quality, texture};
+
+// How a logical render target is fitted into the window it is presented to
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PresentPolicy {
+    // fill the whole window, distorting the image when the aspects differ
+    Stretch,
+    // keep the aspect, with black bars on the sides that are left
+    Letterbox,
+    // scale by a whole factor for crisp pixels, centered in black bars
+    IntegerScale,
+}
+
+// Part of the surface (x, y, width, height) the logical target is drawn to
+pub fn present_viewport(
+    policy: PresentPolicy,
+    logical_size: (u32, u32),
+    surface_size: (u32, u32),
+) -> (f32, f32, f32, f32) {
+    let (logical_width, logical_height) = (logical_size.0 as f32, logical_size.1 as f32);
+    let (surface_width, surface_height) = (surface_size.0 as f32, surface_size.1 as f32);
+    let fit = (surface_width / logical_width).min(surface_height / logical_height);
+    let scale = match policy {
+        PresentPolicy::Stretch => return (0.0, 0.0, surface_width, surface_height),
+        PresentPolicy::Letterbox => fit,
+        // a window smaller than the target can only be fitted by shrinking it
+        PresentPolicy::IntegerScale if fit >= 1.0 => fit.floor(),
+        PresentPolicy::IntegerScale => fit,
+    };
+    let (width, height) = (logical_width * scale, logical_height * scale);
+    (
+        ((surface_width - width) * 0.5).floor(),
+        ((surface_height - height) * 0.5).floor(),
+        width,
+        height,
+    )
+}
+
+// Pipeline copying the logical targets of the views to their surfaces
+pub struct Presenter {
+    // resolution the scene is laid out at, the one of each window without it
+    pub logical_size: Option<(u32, u32)>,
+    pub policy: PresentPolicy,
+    // share of the layout resolution the targets are drawn at
+    pub render_scale: f32,
+    bind_group_layout: wgpu::BindGroupLayout,
+    render_pipeline: wgpu::RenderPipeline,
+    sampler: wgpu::Sampler,
+}
+
+impl Presenter {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        logical_size: Option<(u32, u32)>,
+        policy: PresentPolicy,
+        render_scale: f32,
+    ) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        comparison: false,
+                        filtering: true,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("present_bind_group_layout"),
+        });
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Present Pipeline Layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Present Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shader/present.wgsl").into()),
+        });
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Present Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+        });
+
+        // whole pixel scales keep the pixels sharp, other ones are smoothed
+        let filter = match policy {
+            PresentPolicy::IntegerScale => wgpu::FilterMode::Nearest,
+            _ => wgpu::FilterMode::Linear,
+        };
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: filter,
+            min_filter: filter,
+            ..Default::default()
+        });
+
+        Self {
+            logical_size,
+            policy,
+            render_scale,
+            bind_group_layout,
+            render_pipeline,
+            sampler,
+        }
+    }
+
+    // Resolution a surface of the size is laid out at, before the render scale
+    pub fn layout_size(&self, surface_size: (u32, u32)) -> (u32, u32) {
+        self.logical_size.unwrap_or(surface_size)
+    }
+
+    // Resolution the scene is drawn at for a surface of the size
+    pub fn target_size(&self, surface_size: (u32, u32)) -> (u32, u32) {
+        quality::scaled_size(self.layout_size(surface_size), self.render_scale)
+    }
+
+    // Part of the surface (x, y, width, height) the target is drawn to
+    pub fn viewport(&self, surface_size: (u32, u32)) -> (f32, f32, f32, f32) {
+        present_viewport(self.policy, self.layout_size(surface_size), surface_size)
+    }
+
+    // Offscreen color and depth targets the scene is drawn to before presenting,
+    // for the surface of the config
+    pub fn create_target(
+        &self,
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        depth_format: wgpu::TextureFormat,
+    ) -> LogicalTarget {
+        let size = self.target_size((config.width, config.height));
+        let config = wgpu::SurfaceConfiguration {
+            width: size.0,
+            height: size.1,
+            ..config.clone()
+        };
+        let color_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("logical_target"),
+            size: wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        let color_view = color_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&color_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+            label: Some("present_bind_group"),
+        });
+
+        LogicalTarget {
+            size,
+            color_view,
+            depth_texture: texture::Texture::create_depth_texture_with_format(
+                device,
+                &config,
+                depth_format,
+                "logical_depth_texture",
+            ),
+            bind_group,
+        }
+    }
+
+    // Draw the logical target into the policy viewport of the surface, the
+    // rest of the surface is cleared to black
+    pub fn present(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &LogicalTarget,
+        output_view: &wgpu::TextureView,
+        surface_size: (u32, u32),
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Present Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: output_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        let (x, y, width, height) = self.viewport(surface_size);
+        render_pass.set_viewport(x, y, width, height, 0.0, 1.0);
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &target.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+// Target a view is drawn to at the logical resolution and render scale,
+// whatever the size of its window
+pub struct LogicalTarget {
+    pub size: (u32, u32),
+    pub color_view: wgpu::TextureView,
+    pub depth_texture: texture::Texture,
+    bind_group: wgpu::BindGroup,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOGICAL: (u32, u32) = (320, 180);
+
+    #[test]
+    fn present_in_wider_surface() {
+        let surface = (1000, 360);
+        assert_eq!(
+            present_viewport(PresentPolicy::Stretch, LOGICAL, surface),
+            (0.0, 0.0, 1000.0, 360.0)
+        );
+        // bars on the left and right
+        assert_eq!(
+            present_viewport(PresentPolicy::Letterbox, LOGICAL, surface),
+            (180.0, 0.0, 640.0, 360.0)
+        );
+        assert_eq!(
+            present_viewport(PresentPolicy::IntegerScale, LOGICAL, (1000, 400)),
+            (180.0, 20.0, 640.0, 360.0)
+        );
+    }
+
+    #[test]
+    fn present_in_taller_surface() {
+        let surface = (800, 900);
+        assert_eq!(
+            present_viewport(PresentPolicy::Stretch, LOGICAL, surface),
+            (0.0, 0.0, 800.0, 900.0)
+        );
+        // bars on the top and bottom
+        assert_eq!(
+            present_viewport(PresentPolicy::Letterbox, LOGICAL, surface),
+            (0.0, 225.0, 800.0, 450.0)
+        );
+        // scaled by 2 of the 2.5 that fit
+        assert_eq!(
+            present_viewport(PresentPolicy::IntegerScale, LOGICAL, surface),
+            (80.0, 270.0, 640.0, 360.0)
+        );
+    }
+
+    #[test]
+    fn present_integer_scale_below_one() {
+        // a surface smaller than the logical size shrinks it like letterboxing
+        let surface = (160, 100);
+        let expected = (0.0, 5.0, 160.0, 90.0);
+        assert_eq!(
+            present_viewport(PresentPolicy::IntegerScale, LOGICAL, surface),
+            expected
+        );
+        assert_eq!(
+            present_viewport(PresentPolicy::Letterbox, LOGICAL, surface),
+            expected
+        );
+        // the offsets are floored to whole pixels
+        assert_eq!(
+            present_viewport(PresentPolicy::IntegerScale, LOGICAL, (160, 101)),
+            (0.0, 5.0, 160.0, 90.0)
+        );
+    }
+}
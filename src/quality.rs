@@ -0,0 +1,180 @@
+use std::time::Duration;
+
+// change of the dynamic render scale at a time
+const SCALE_STEP: f32 = 0.05;
+// frames between two changes of the dynamic render scale, as each one
+// recreates the scene targets
+const SCALE_INTERVAL_FRAMES: u32 = 30;
+// the scale goes back up when the frames take less than this share of the budget
+const SCALE_HEADROOM: f32 = 0.8;
+
+// Graphics options an application can offer to its users, switched while the
+// scene runs
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct QualitySettings {
+    // share of the resolution of the views, or of the logical resolution, the
+    // scene is drawn at before being scaled up to them
+    pub render_scale: f32,
+    // frame time to keep under by lowering the render scale down to
+    // min_render_scale, it goes back up to render_scale when there is room
+    pub frame_budget: Option<Duration>,
+    pub min_render_scale: f32,
+}
+
+impl Default for QualitySettings {
+    fn default() -> Self {
+        QualityPreset::High.settings()
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum QualityPreset {
+    Low,
+    Medium,
+    #[default]
+    High,
+}
+
+impl QualityPreset {
+    pub fn settings(self) -> QualitySettings {
+        let render_scale = match self {
+            QualityPreset::Low => 0.5,
+            QualityPreset::Medium => 0.75,
+            QualityPreset::High => 1.0,
+        };
+        QualitySettings {
+            render_scale,
+            frame_budget: None,
+            min_render_scale: 0.5,
+        }
+    }
+
+    // Preset of the render scale, the closest one above for other scales
+    pub fn from_scale(render_scale: f32) -> Self {
+        if render_scale <= 0.5 {
+            QualityPreset::Low
+        } else if render_scale <= 0.75 {
+            QualityPreset::Medium
+        } else {
+            QualityPreset::High
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            QualityPreset::Low => QualityPreset::Medium,
+            QualityPreset::Medium => QualityPreset::High,
+            QualityPreset::High => QualityPreset::Low,
+        }
+    }
+}
+
+// Render scale following the frame time, when there is a frame budget
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct DynamicResolution {
+    pub scale: f32,
+    // average frame time in seconds, smoothed over the last frames
+    average: f32,
+    frames: u32,
+}
+
+impl DynamicResolution {
+    pub fn new(settings: &QualitySettings) -> Self {
+        Self {
+            scale: settings.render_scale,
+            average: 0.0,
+            frames: 0,
+        }
+    }
+
+    // Account for the time of the last frame, true when the scale changed
+    pub fn update(&mut self, dt: Duration, settings: &QualitySettings) -> bool {
+        let budget = match settings.frame_budget {
+            Some(budget) => budget.as_secs_f32(),
+            None => return false,
+        };
+        self.average = match self.frames {
+            0 => dt.as_secs_f32(),
+            _ => self.average * 0.9 + dt.as_secs_f32() * 0.1,
+        };
+        self.frames = self.frames.saturating_add(1);
+        if self.frames < SCALE_INTERVAL_FRAMES {
+            return false;
+        }
+
+        let min_scale = settings.min_render_scale.min(settings.render_scale);
+        let scale = if self.average > budget {
+            (self.scale - SCALE_STEP).max(min_scale)
+        } else if self.average < budget * SCALE_HEADROOM {
+            (self.scale + SCALE_STEP).min(settings.render_scale)
+        } else {
+            self.scale
+        };
+        if scale == self.scale {
+            return false;
+        }
+        // the average restarts at the new scale
+        self.scale = scale;
+        self.frames = 0;
+        true
+    }
+}
+
+// Size a target is drawn at for the scale, at least one pixel
+pub(crate) fn scaled_size(size: (u32, u32), scale: f32) -> (u32, u32) {
+    let scaled = |length: u32| ((length as f32 * scale).round() as u32).max(1);
+    (scaled(size.0), scaled(size.1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(dynamic: &mut DynamicResolution, settings: &QualitySettings, millis: u64) -> bool {
+        // every frame is accounted for, changed or not
+        let changes: Vec<bool> = (0..SCALE_INTERVAL_FRAMES)
+            .map(|_| dynamic.update(Duration::from_millis(millis), settings))
+            .collect();
+        changes.contains(&true)
+    }
+
+    #[test]
+    fn dynamic_scale_follows_frame_budget() {
+        let settings = QualitySettings {
+            frame_budget: Some(Duration::from_millis(16)),
+            ..QualityPreset::High.settings()
+        };
+        let mut dynamic = DynamicResolution::new(&settings);
+
+        // slow frames lower the scale a step at a time, down to the minimum
+        assert!(frames(&mut dynamic, &settings, 30));
+        assert_eq!(dynamic.scale, 1.0 - SCALE_STEP);
+        for _ in 0..20 {
+            frames(&mut dynamic, &settings, 30);
+        }
+        assert_eq!(dynamic.scale, 0.5);
+        assert!(!frames(&mut dynamic, &settings, 30));
+
+        // frames close to the budget keep it
+        assert!(!frames(&mut dynamic, &settings, 15));
+        // fast ones raise it back up to the render scale
+        for _ in 0..20 {
+            frames(&mut dynamic, &settings, 5);
+        }
+        assert_eq!(dynamic.scale, 1.0);
+    }
+
+    #[test]
+    fn static_scale_without_budget() {
+        let settings = QualityPreset::Medium.settings();
+        let mut dynamic = DynamicResolution::new(&settings);
+        assert!(!frames(&mut dynamic, &settings, 100));
+        assert_eq!(dynamic.scale, 0.75);
+    }
+
+    #[test]
+    fn scaled_sizes() {
+        assert_eq!(scaled_size((1280, 720), 0.75), (960, 540));
+        assert_eq!(scaled_size((3, 1), 0.1), (1, 1));
+    }
+}
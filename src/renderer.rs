@@ -1,5 +1,6 @@
-use crate::{camera, light, object, texture};
+use crate::{binding, bounds, camera, globals, jobs, light, material_graph, object, texture};
 use std::ops::Range;
+use std::sync::{mpsc, Arc};
 use wgpu::util::DeviceExt;
 
 pub trait Vertex {
@@ -46,10 +47,19 @@ impl Vertex for ModelVertex {
 pub struct InstanceRaw {
     pub model: [[f32; 4]; 4],
     pub normal: [[f32; 3]; 3],
+    // Free per instance value for shaders to offset animations (wind sway,
+    // flipbook frames...) so that instances don't all move in lockstep
+    pub phase: f32,
+    // Share of the pixels of the instance left out in a screen-door pattern,
+    // from 0 drawn to 1 hidden, e.g. to fade an LOD out or an instance the
+    // camera goes through. Negative values leave out the pixels the opposite
+    // value keeps, so an LOD fading in with fade - 1 fills the holes of one
+    // fading out with fade.
+    pub fade: f32,
 }
 
 impl InstanceRaw {
-    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+    pub(crate) fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
         use std::mem;
         wgpu::VertexBufferLayout {
             array_stride: mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
@@ -98,6 +108,16 @@ impl InstanceRaw {
                     shader_location: 11,
                     format: wgpu::VertexFormat::Float32x3,
                 },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 25]>() as wgpu::BufferAddress,
+                    shader_location: 12,
+                    format: wgpu::VertexFormat::Float32,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 26]>() as wgpu::BufferAddress,
+                    shader_location: 13,
+                    format: wgpu::VertexFormat::Float32,
+                },
             ],
         }
     }
@@ -107,6 +127,7 @@ pub struct Model {
     pub meshes: Vec<Mesh>,
     pub materials: Option<Vec<Material>>,
     pub material_layout: Option<wgpu::BindGroupLayout>,
+    pub bounds: bounds::Aabb,
 }
 
 pub struct Mesh {
@@ -125,6 +146,68 @@ pub struct Geometry {
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
     pub num_elements: u32,
+    pub bounds: bounds::Aabb,
+}
+
+impl Material {
+    pub fn create_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        // This is only for TextureSampleType::Depth
+                        comparison: false,
+                        // This should be true if the sample_type of the texture is:
+                        //     TextureSampleType::Float { filterable: true }
+                        // Otherwise you'll get an error.
+                        filtering: true,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("material_bind_group_layout"),
+        })
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        name: String,
+        diffuse_texture: texture::Texture,
+    ) -> Self {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
+                },
+            ],
+            label: None,
+        });
+
+        Self {
+            name,
+            diffuse_texture,
+            bind_group,
+        }
+    }
 }
 
 impl Model {
@@ -132,45 +215,14 @@ impl Model {
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         object: object::Object,
-    ) -> Result<Self, ()> {
+    ) -> anyhow::Result<Self> {
         let (obj_models, textures) = (object.models, object.textures);
 
         let mut material_flag = false;
 
-        let material_layout = if let Some(_) = &textures {
+        let material_layout = if textures.is_some() {
             material_flag = true;
-
-            let material_layout =
-                device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                    entries: &[
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 0,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Texture {
-                                multisampled: false,
-                                view_dimension: wgpu::TextureViewDimension::D2,
-                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                            },
-                            count: None,
-                        },
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 1,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Sampler {
-                                // This is only for TextureSampleType::Depth
-                                comparison: false,
-                                // This should be true if the sample_type of the texture is:
-                                //     TextureSampleType::Float { filterable: true }
-                                // Otherwise you'll get an error.
-                                filtering: true,
-                            },
-                            count: None,
-                        },
-                    ],
-                    label: Some("material_bind_group_layout"),
-                });
-
-            Some(material_layout)
+            Some(Material::create_layout(device))
         } else {
             None
         };
@@ -184,31 +236,14 @@ impl Model {
                     queue,
                     diffuse_img,
                     Some(diffuse_label.as_str()),
-                )
-                .unwrap();
-
-                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-                    layout: &material_layout.as_ref().unwrap(),
-                    entries: &[
-                        wgpu::BindGroupEntry {
-                            binding: 0,
-                            resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 1,
-                            resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                        },
-                    ],
-                    label: None,
-                });
-
-                let material_name = name.clone();
+                )?;
 
-                materials.push(Material {
-                    name: material_name,
+                materials.push(Material::new(
+                    device,
+                    material_layout.as_ref().unwrap(),
+                    name.clone(),
                     diffuse_texture,
-                    bind_group,
-                });
+                ));
             }
 
             Some(materials)
@@ -217,6 +252,7 @@ impl Model {
         };
 
         let mut meshes = Vec::new();
+        let mut model_bounds = bounds::Aabb::empty();
         for m in obj_models {
             let mut vertices = Vec::new();
             for i in 0..m.mesh.positions.len() / 3 {
@@ -246,30 +282,36 @@ impl Model {
                 usage: wgpu::BufferUsages::INDEX,
             });
 
+            let geometry_bounds =
+                bounds::Aabb::from_points(vertices.iter().map(|vertex| vertex.position.into()));
+            model_bounds = model_bounds.union(&geometry_bounds);
+
             let geometry = Geometry {
                 name: m.name,
                 vertex_buffer,
                 index_buffer,
                 num_elements: m.mesh.indices.len() as u32,
+                bounds: geometry_bounds,
             };
 
             if material_flag {
                 meshes.push(Mesh {
-                    geometry: geometry,
+                    geometry,
                     material_id: Some(m.mesh.material_id.unwrap_or(0)),
                 });
             } else {
                 meshes.push(Mesh {
-                    geometry: geometry,
+                    geometry,
                     material_id: None,
                 });
             }
         }
 
         Ok(Self {
-            meshes: meshes,
-            materials: materials,
-            material_layout: material_layout,
+            meshes,
+            materials,
+            material_layout,
+            bounds: model_bounds,
         })
     }
 }
@@ -279,89 +321,269 @@ pub struct ModelRenderer {
     pub render_pipeline: wgpu::RenderPipeline,
     pub instance_buffer: Option<wgpu::Buffer>,
     pub instance_length: Option<usize>,
+    // instances the buffer has room for
+    instance_capacity: usize,
+    // drawn with instead of the render pipeline in the debug views
+    pub(crate) debug_pipeline: Option<wgpu::RenderPipeline>,
+    // built on a worker thread, the render pipeline is a placeholder until then
+    pending_pipeline: Option<mpsc::Receiver<wgpu::RenderPipeline>>,
+}
+
+// Unlit magenta drawn with the vertex stage of the model while its own
+// pipeline is built
+const PLACEHOLDER_FRAGMENT: &str = "
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(1.0, 0.0, 1.0, 1.0);
+}
+";
+
+// What the pipelines of the scene are built against: its device, the formats
+// of the view targets and the groups shared by every model
+pub(crate) struct PipelineInputs<'a> {
+    pub(crate) device: &'a Arc<wgpu::Device>,
+    pub(crate) queue: &'a wgpu::Queue,
+    pub(crate) color_format: wgpu::TextureFormat,
+    pub(crate) depth_format: wgpu::TextureFormat,
+    pub(crate) registry: &'a binding::BindingRegistry,
+    pub(crate) camera: &'a camera::Camera,
+    pub(crate) light: &'a light::Light,
+    pub(crate) globals: &'a globals::Globals,
+}
+
+impl PipelineInputs<'_> {
+    // Layout with the material, camera, lightning and globals at their
+    // canonical groups
+    pub(crate) fn layout(
+        &self,
+        label: &str,
+        material_layout: Option<&wgpu::BindGroupLayout>,
+    ) -> wgpu::PipelineLayout {
+        self.registry.pipeline_layout(
+            self.device,
+            Some(label),
+            [
+                material_layout,
+                Some(&self.camera.bind_group_layout),
+                Some(&self.light.bind_group_layout),
+                Some(&self.globals.bind_group_layout),
+            ],
+        )
+    }
+}
+
+// What a render pipeline of a model is built from, without borrowing from the
+// scene
+struct PipelineParts {
+    layout: wgpu::PipelineLayout,
+    vertex_layouts: Vec<wgpu::VertexBufferLayout<'static>>,
+    color_format: wgpu::TextureFormat,
+    depth_format: wgpu::TextureFormat,
+}
+
+impl PipelineParts {
+    fn build(&self, device: &wgpu::Device, source: &str) -> wgpu::RenderPipeline {
+        ModelRenderer::create_render_pipeline(
+            device,
+            &self.layout,
+            self.color_format,
+            Some(self.depth_format),
+            &self.vertex_layouts,
+            wgpu::ShaderModuleDescriptor {
+                label: Some("Normal Shader"),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            },
+        )
+    }
 }
 
 impl ModelRenderer {
     pub fn new_renderer(
         model: Model,
-        device: &wgpu::Device,
-        config: &wgpu::SurfaceConfiguration,
-        camera: &camera::Camera,
-        light: &light::Light,
+        inputs: &PipelineInputs,
         shader_file: std::borrow::Cow<str>,
         instance_data: Option<Vec<InstanceRaw>>,
         instance_length: Option<usize>,
     ) -> ModelRenderer {
-        let instance_mode = if let Some(_) = &instance_data {
-            true
-        } else {
-            false
-        };
+        let parts = ModelRenderer::pipeline_parts(&model, inputs, instance_data.is_some());
+        let render_pipeline = parts.build(inputs.device, &globals::preprocess(&shader_file));
+        ModelRenderer::with_pipeline(
+            model,
+            inputs.device,
+            render_pipeline,
+            instance_data,
+            instance_length,
+        )
+    }
 
-        let render_pipeline = {
-            // declare a dynamic array for bind group layouts
-            let mut bind_group_layouts = Vec::new();
-            if let Some(material_layout) = model.material_layout.as_ref() {
-                bind_group_layouts.push(material_layout);
-            }
-            // add camera and lightning
-            bind_group_layouts.push(&camera.bind_group_layout);
-            bind_group_layouts.push(&light.bind_group_layout);
-
-            let render_pipeline_layout =
-                device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    label: Some("Render Pipeline Layout"),
-                    bind_group_layouts: &bind_group_layouts[..],
-                    push_constant_ranges: &[],
-                });
-            let shader = wgpu::ShaderModuleDescriptor {
-                label: Some("Normal Shader"),
-                source: wgpu::ShaderSource::Wgsl(shader_file),
-            };
+    // new_renderer building the pipeline on a worker thread, as compiling the
+    // shader can take long enough to skip frames. The model is drawn unlit in
+    // magenta until poll_pipeline gets it.
+    pub(crate) fn new_renderer_in_background(
+        model: Model,
+        inputs: &PipelineInputs,
+        shader_file: std::borrow::Cow<str>,
+        instance_data: Option<Vec<InstanceRaw>>,
+        instance_length: Option<usize>,
+    ) -> ModelRenderer {
+        let device = inputs.device;
+        let instanced = instance_data.is_some();
+        let parts = ModelRenderer::pipeline_parts(&model, inputs, instanced);
+        let placeholder = format!(
+            "[[block]]
+struct Camera {{
+    view_position: vec4<f32>;
+    view_proj: mat4x4<f32>;
+}};
+[[group({}), binding(0)]]
+var<uniform> camera: Camera;
+
+{}{}{}",
+            binding::CAMERA_GROUP,
+            material_graph::VERTEX_IO,
+            if instanced {
+                material_graph::VERTEX_INSTANCED
+            } else {
+                material_graph::VERTEX
+            },
+            PLACEHOLDER_FRAGMENT
+        );
+        let placeholder = parts.build(device, &placeholder);
+
+        let (sender, receiver) = mpsc::channel();
+        let source = globals::preprocess(&shader_file);
+        let job_device = device.clone();
+        jobs::spawn_job(move || {
+            // the scene may be gone by the time it is built
+            let _ = sender.send(parts.build(&job_device, &source));
+        });
 
-            let mut vertex_layouts = Vec::new();
-            vertex_layouts.push(ModelVertex::desc());
-            if instance_mode {
-                vertex_layouts.push(InstanceRaw::desc());
-            }
+        let mut renderer = ModelRenderer::with_pipeline(
+            model,
+            device,
+            placeholder,
+            instance_data,
+            instance_length,
+        );
+        renderer.pending_pipeline = Some(receiver);
+        renderer
+    }
 
-            ModelRenderer::create_render_pipeline(
-                &device,
-                &render_pipeline_layout,
-                config.format,
-                Some(texture::Texture::DEPTH_FORMAT),
-                &vertex_layouts[..],
-                shader,
-            )
+    // Swap the placeholder for the pipeline built in the background once it is
+    // ready
+    pub(crate) fn poll_pipeline(&mut self) {
+        let pending = match self.pending_pipeline.as_ref() {
+            Some(pending) => pending,
+            None => return,
         };
+        match pending.try_recv() {
+            Ok(pipeline) => self.render_pipeline = pipeline,
+            Err(mpsc::TryRecvError::Empty) => return,
+            // the job panicked, e.g. on a shader validation error, and was
+            // logged: the placeholder stays
+            Err(mpsc::TryRecvError::Disconnected) => {}
+        }
+        self.pending_pipeline = None;
+    }
 
-        let instance_buffer = if instance_mode {
-            Some(
-                device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("Instance Buffer"),
-                    contents: bytemuck::cast_slice(&instance_data.unwrap()),
-                    usage: wgpu::BufferUsages::VERTEX,
-                }),
-            )
-        } else {
-            None
-        };
+    fn pipeline_parts(model: &Model, inputs: &PipelineInputs, instanced: bool) -> PipelineParts {
+        let layout = inputs.layout("Render Pipeline Layout", model.material_layout.as_ref());
+
+        let mut vertex_layouts = Vec::new();
+        vertex_layouts.push(ModelVertex::desc());
+        if instanced {
+            vertex_layouts.push(InstanceRaw::desc());
+        }
+
+        PipelineParts {
+            layout,
+            vertex_layouts,
+            color_format: inputs.color_format,
+            depth_format: inputs.depth_format,
+        }
+    }
+
+    fn with_pipeline(
+        model: Model,
+        device: &wgpu::Device,
+        render_pipeline: wgpu::RenderPipeline,
+        instance_data: Option<Vec<InstanceRaw>>,
+        instance_length: Option<usize>,
+    ) -> ModelRenderer {
+        let instance_capacity = instance_data.as_ref().map_or(0, Vec::len);
+        let instance_buffer =
+            instance_data.map(|instance_data| Self::create_instance_buffer(device, &instance_data));
 
         ModelRenderer {
-            model: model,
-            render_pipeline: render_pipeline,
-            instance_buffer: instance_buffer,
-            instance_length: instance_length,
+            model,
+            render_pipeline,
+            instance_buffer,
+            instance_length,
+            instance_capacity,
+            debug_pipeline: None,
+            pending_pipeline: None,
         }
     }
 
-    fn create_render_pipeline(
+    fn create_instance_buffer(device: &wgpu::Device, instances: &[InstanceRaw]) -> wgpu::Buffer {
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Instance Buffer"),
+            contents: bytemuck::cast_slice(instances),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        })
+    }
+
+    // Draw the instanced model at other instances, written over the previous
+    // ones while they fit in the buffer
+    pub(crate) fn set_instances(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        instances: &[InstanceRaw],
+    ) {
+        match self.instance_buffer.as_ref() {
+            Some(buffer) if instances.len() <= self.instance_capacity => {
+                if !instances.is_empty() {
+                    queue.write_buffer(buffer, 0, bytemuck::cast_slice(instances));
+                }
+            }
+            _ => {
+                self.instance_buffer = Some(Self::create_instance_buffer(device, instances));
+                self.instance_capacity = instances.len();
+            }
+        }
+        self.instance_length = Some(instances.len());
+    }
+
+    pub fn create_render_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        color_format: wgpu::TextureFormat,
+        depth_format: Option<wgpu::TextureFormat>,
+        vertex_layouts: &[wgpu::VertexBufferLayout],
+        shader: wgpu::ShaderModuleDescriptor,
+    ) -> wgpu::RenderPipeline {
+        Self::create_render_pipeline_with_mode(
+            device,
+            layout,
+            color_format,
+            depth_format,
+            vertex_layouts,
+            shader,
+            wgpu::PolygonMode::Fill,
+        )
+    }
+
+    // Same pipeline drawing the polygons as lines or points, which needs
+    // Features::POLYGON_MODE_LINE or POLYGON_MODE_POINT
+    pub fn create_render_pipeline_with_mode(
         device: &wgpu::Device,
         layout: &wgpu::PipelineLayout,
         color_format: wgpu::TextureFormat,
         depth_format: Option<wgpu::TextureFormat>,
         vertex_layouts: &[wgpu::VertexBufferLayout],
         shader: wgpu::ShaderModuleDescriptor,
+        polygon_mode: wgpu::PolygonMode,
     ) -> wgpu::RenderPipeline {
         let shader = device.create_shader_module(&shader);
 
@@ -390,8 +612,7 @@ impl ModelRenderer {
                 strip_index_format: None,
                 front_face: wgpu::FrontFace::Ccw,
                 cull_mode: Some(wgpu::Face::Back),
-                // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-                polygon_mode: wgpu::PolygonMode::Fill,
+                polygon_mode,
                 // Requires Features::DEPTH_CLAMPING
                 clamp_depth: false,
                 // Requires Features::CONSERVATIVE_RASTERIZATION
@@ -414,8 +635,18 @@ impl ModelRenderer {
 }
 
 pub trait DrawModel<'a> {
+    // bind_groups are bound at their index, group 0 being replaced by the
+    // material of each mesh that has one
     fn draw_model(&mut self, model: &'a ModelRenderer, bind_groups: &'a [&'a wgpu::BindGroup]);
 
+    // draw_model with another pipeline built for the layouts of the model
+    fn draw_model_with_pipeline(
+        &mut self,
+        model: &'a ModelRenderer,
+        pipeline: &'a wgpu::RenderPipeline,
+        bind_groups: &'a [&'a wgpu::BindGroup],
+    );
+
     fn draw_mesh_instanced(
         &mut self,
         mesh: &'a Mesh,
@@ -433,9 +664,23 @@ where
         &mut self,
         model_renderer: &'b ModelRenderer,
         bind_groups: &'b [&'b wgpu::BindGroup],
+    ) {
+        // the debug view replaces the shader of the model
+        let pipeline = model_renderer
+            .debug_pipeline
+            .as_ref()
+            .unwrap_or(&model_renderer.render_pipeline);
+        self.draw_model_with_pipeline(model_renderer, pipeline, bind_groups);
+    }
+
+    fn draw_model_with_pipeline(
+        &mut self,
+        model_renderer: &'b ModelRenderer,
+        pipeline: &'b wgpu::RenderPipeline,
+        bind_groups: &'b [&'b wgpu::BindGroup],
     ) {
         // set pipeline
-        self.set_pipeline(&model_renderer.render_pipeline);
+        self.set_pipeline(pipeline);
 
         // check if there is more than one instance to draw
         let instances_to_draw = if let Some(instance_range) = model_renderer.instance_length {
@@ -481,18 +726,15 @@ where
             wgpu::IndexFormat::Uint32,
         );
 
-        // set material bind group
-        let mut offset = 0;
-        if let Some(material) = material_bind_group {
-            self.set_bind_group(0, material, &[]);
-            offset += 1;
+        // set the bind groups, the material taking the place of the empty one
+        for (index, group) in bind_groups.iter().enumerate() {
+            let group = match material_bind_group {
+                Some(material) if index as u32 == binding::MATERIAL_GROUP => material,
+                _ => group,
+            };
+            self.set_bind_group(index as u32, group, &[]);
         }
 
-        // set light bind group
-        bind_groups.iter().enumerate().for_each(|(index, group)| {
-            self.set_bind_group(index as u32 + offset, *group, &[]);
-        });
-
         // draw the mesh
         self.draw_indexed(0..mesh.geometry.num_elements, 0, instances);
     }
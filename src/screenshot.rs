@@ -0,0 +1,135 @@
+use crate::texture;
+use anyhow::*;
+use std::path::PathBuf;
+
+// A target at the size of the scene target of a view, which is drawn again into
+// it, then copied to a buffer read at the next frame and saved as a png
+pub(crate) struct Screenshot {
+    format: wgpu::TextureFormat,
+    size: (u32, u32),
+    color_texture: wgpu::Texture,
+    pub color_view: wgpu::TextureView,
+    pub depth_texture: texture::Texture,
+    buffer: wgpu::Buffer,
+    // bytes of each row in the buffer, padded to the copy alignment
+    padded_row: u32,
+}
+
+impl Screenshot {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        depth_format: wgpu::TextureFormat,
+        size: (u32, u32),
+    ) -> Self {
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format,
+            width: size.0,
+            height: size.1,
+            present_mode: wgpu::PresentMode::Fifo,
+        };
+        let color_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Screenshot Texture"),
+            size: wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: config.usage,
+        });
+        let color_view = color_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth_texture = texture::Texture::create_depth_texture_with_format(
+            device,
+            &config,
+            depth_format,
+            "screenshot_depth_texture",
+        );
+        let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_row = (size.0 * 4).div_ceil(alignment) * alignment;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Screenshot Buffer"),
+            size: padded_row as u64 * size.1 as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            format,
+            size,
+            color_texture,
+            color_view,
+            depth_texture,
+            buffer,
+            padded_row,
+        }
+    }
+
+    // Copy the drawn target to the buffer
+    pub fn copy(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.color_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &self.buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(self.padded_row),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: self.size.0,
+                height: self.size.1,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    // Image copied in the last frame, as shown on screen, its frame is done by
+    // now or close to it
+    pub fn read(&self, device: &wgpu::Device) -> Result<image::RgbaImage> {
+        let bgra = match self.format {
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+            format => bail!("can't save screenshots of {:?} targets", format),
+        };
+        let buffer_slice = self.buffer.slice(..);
+        let mapping = buffer_slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        pollster::block_on(mapping)?;
+
+        let (width, height) = self.size;
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        for row in buffer_slice
+            .get_mapped_range()
+            .chunks(self.padded_row as usize)
+        {
+            pixels.extend_from_slice(&row[..(width * 4) as usize]);
+        }
+        self.buffer.unmap();
+        if bgra {
+            for pixel in pixels.chunks_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        image::RgbaImage::from_raw(width, height, pixels).context("screenshot buffer is too small")
+    }
+}
+
+// First lens-screenshot-<n>.png missing from the working directory, so that
+// the previous screenshots are kept
+pub(crate) fn next_path() -> PathBuf {
+    (1..)
+        .map(|n| PathBuf::from(format!("lens-screenshot-{}.png", n)))
+        .find(|path| !path.exists())
+        .unwrap()
+}
@@ -0,0 +1,188 @@
+use crate::texture::Texture;
+use anyhow::*;
+use cgmath::*;
+
+// bytes of the sums of one row, 9 coefficients padded to vec4
+const ROW_SIZE: u64 = 9 * 16;
+
+// Light of an environment as its first 9 real spherical harmonics, one rgb
+// coefficient per harmonic in the (0,0), (1,-1), (1,0), (1,1), (2,-2), (2,-1),
+// (2,0), (2,1), (2,2) order
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SphericalHarmonics {
+    pub coefficients: [Vector3<f32>; 9],
+}
+
+impl SphericalHarmonics {
+    fn basis(n: Vector3<f32>) -> [f32; 9] {
+        [
+            0.282095,
+            0.488603 * n.y,
+            0.488603 * n.z,
+            0.488603 * n.x,
+            1.092548 * n.x * n.y,
+            1.092548 * n.y * n.z,
+            0.315392 * (3.0 * n.z * n.z - 1.0),
+            1.092548 * n.x * n.z,
+            0.546274 * (n.x * n.x - n.y * n.y),
+        ]
+    }
+
+    // Light reaching a surface of the given normal, divided by pi so that it can
+    // be multiplied with the surface color like the ambient term of the shading
+    pub fn irradiance(&self, normal: Vector3<f32>) -> Vector3<f32> {
+        // convolution with the cosine lobe of each band, over pi
+        const BANDS: [f32; 9] = [
+            1.0,
+            2.0 / 3.0,
+            2.0 / 3.0,
+            2.0 / 3.0,
+            0.25,
+            0.25,
+            0.25,
+            0.25,
+            0.25,
+        ];
+        Self::basis(normal.normalize())
+            .iter()
+            .zip(BANDS.iter().zip(self.coefficients.iter()))
+            .map(|(y, (band, coefficient))| coefficient * (y * band))
+            .sum()
+    }
+
+    // Average light of the whole environment
+    pub fn ambient(&self) -> Vector3<f32> {
+        self.coefficients[0] * 0.282095
+    }
+}
+
+// Project a cube texture, e.g. from Texture::cube_from_images, onto spherical
+// harmonics on the GPU
+pub fn project_cubemap(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    cube: &Texture,
+) -> Result<SphericalHarmonics> {
+    ensure!(
+        cube.size.depth_or_array_layers == 6 && cube.size.width == cube.size.height,
+        "expected the 6 square faces of a cube, got {}x{}x{}",
+        cube.size.width,
+        cube.size.height,
+        cube.size.depth_or_array_layers
+    );
+    let rows = 6 * cube.size.height as u64;
+
+    // the faces are read as layers, cube views can't be loaded from
+    let faces = cube.texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::D2Array),
+        ..Default::default()
+    });
+    // every row is written by the shader
+    let output = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("SH Rows Buffer"),
+        size: rows * ROW_SIZE,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    let readback = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("SH Readback Buffer"),
+        size: rows * ROW_SIZE,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2Array,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+        label: Some("sh_bind_group_layout"),
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&faces),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: output.as_entire_binding(),
+            },
+        ],
+        label: Some("sh_bind_group"),
+    });
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("SH Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: Some("SH Shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("shader/sh_project.wgsl").into()),
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("SH Pipeline"),
+        layout: Some(&layout),
+        module: &module,
+        entry_point: "main",
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("SH Encoder"),
+    });
+    {
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("SH Pass"),
+        });
+        pass.set_pipeline(&pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        // a row per invocation, the workgroups are 64 wide
+        pass.dispatch(cube.size.height.div_ceil(64), 6, 1);
+    }
+    encoder.copy_buffer_to_buffer(&output, 0, &readback, 0, rows * ROW_SIZE);
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let buffer_slice = readback.slice(..);
+    let mapping = buffer_slice.map_async(wgpu::MapMode::Read);
+    device.poll(wgpu::Maintain::Wait);
+    pollster::block_on(mapping)?;
+
+    let mut coefficients = [Vector3::zero(); 9];
+    let mut solid_angle = 0.0;
+    {
+        let mapped = buffer_slice.get_mapped_range();
+        let sums: &[[f32; 4]] = bytemuck::cast_slice(&mapped);
+        for row in sums.chunks_exact(9) {
+            for (coefficient, sum) in coefficients.iter_mut().zip(row) {
+                *coefficient += Vector3::new(sum[0], sum[1], sum[2]);
+            }
+            solid_angle += row[0][3];
+        }
+    }
+    readback.unmap();
+
+    // the texels cover the sphere up to rounding, rescale to exactly 4 pi
+    let scale = 4.0 * std::f32::consts::PI / solid_angle;
+    Ok(SphericalHarmonics {
+        coefficients: coefficients.map(|coefficient| coefficient * scale),
+    })
+}
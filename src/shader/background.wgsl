@@ -0,0 +1,78 @@
+// Background drawn on a unit sphere that follows the camera, behind the models
+
+[[block]]
+struct Background {
+    // 1: vertical gradient, 2: skybox, 3: procedural sky
+    mode: u32;
+    top: vec4<f32>;
+    bottom: vec4<f32>;
+    sun_direction: vec4<f32>;
+};
+[[group(0), binding(0)]]
+var<uniform> background: Background;
+[[group(0), binding(1)]]
+var t_skybox: texture_cube<f32>;
+[[group(0), binding(2)]]
+var s_skybox: sampler;
+
+[[block]]
+struct Camera {
+    view_position: vec4<f32>;
+    view_proj: mat4x4<f32>;
+};
+[[group(1), binding(0)]]
+var<uniform> camera: Camera;
+
+#import lens::globals
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] direction: vec3<f32>;
+    // y and w of the clip position, divided per fragment into the height on
+    // the view, whatever the viewport and the target it is drawn to
+    [[location(1)]] clip_yw: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.direction = model.position;
+    let clip_position = camera.view_proj * vec4<f32>(camera.view_position.xyz + model.position, 1.0);
+    // push the sphere right in front of the far plane, behind everything else
+    out.clip_position = vec4<f32>(clip_position.xy, clip_position.w * 0.99999, clip_position.w);
+    out.clip_yw = clip_position.yw;
+    return out;
+}
+
+fn sky(direction: vec3<f32>) -> vec3<f32> {
+    let zenith = vec3<f32>(0.1, 0.3, 0.7);
+    let horizon = vec3<f32>(0.6, 0.75, 0.9);
+    let ground = vec3<f32>(0.3, 0.28, 0.25);
+    var color: vec3<f32>;
+    if (direction.y >= 0.0) {
+        color = mix(horizon, zenith, sqrt(direction.y));
+    } else {
+        color = mix(horizon, ground, min(-direction.y * 4.0, 1.0));
+    }
+
+    // sun disk with a glow around it
+    let sun = max(dot(direction, background.sun_direction.xyz), 0.0);
+    return color + vec3<f32>(1.0, 0.9, 0.7) * (pow(sun, 800.0) * 4.0 + pow(sun, 8.0) * 0.2);
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let direction = normalize(in.direction);
+    if (background.mode == 1u) {
+        let t = clamp(0.5 - 0.5 * in.clip_yw.x / in.clip_yw.y, 0.0, 1.0);
+        return mix(background.top, background.bottom, t);
+    }
+    if (background.mode == 2u) {
+        return textureSample(t_skybox, s_skybox, direction);
+    }
+    return vec4<f32>(sky(direction), 1.0);
+}
@@ -0,0 +1,32 @@
+// False color views of the scene, drawn in place of the shaders of the models.
+// The DEBUG_* values are filled in by debug_view::debug_pipeline, the vertex
+// stage, the camera, the dither function and the palette are declared before
+// this.
+
+// 1 depth, 2 normals, 3 overdraw, 4 wireframe
+let MODE: i32 = DEBUG_MODE;
+// distance from the camera mapped to the middle of the palette
+let DEPTH_SCALE: f32 = DEBUG_DEPTH_SCALE;
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    // faded instances leave the same pixels out as when shaded
+    if (dither_fade(in.clip_position.xy, in.fade)) {
+        discard;
+    }
+    if (MODE == 1) {
+        // near is bright, far fades to the dark end
+        let distance = length(in.world_position - camera.view_position.xyz);
+        return vec4<f32>(palette(exp(-distance / DEPTH_SCALE * 0.6931472)), 1.0);
+    }
+    if (MODE == 3) {
+        // added up by the blending of the overdraw target
+        return vec4<f32>(1.0);
+    }
+    if (MODE == 4) {
+        // lines drawn by the pipeline, lighter where they face the camera
+        let facing = abs(dot(normalize(in.world_normal), normalize(camera.view_position.xyz - in.world_position)));
+        return vec4<f32>(vec3<f32>(0.4 + 0.6 * facing), 1.0);
+    }
+    return vec4<f32>(normalize(in.world_normal) * 0.5 + 0.5, 1.0);
+}
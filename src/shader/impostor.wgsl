@@ -0,0 +1,70 @@
+// Impostor of a model, a quad turned toward the camera around the vertical axis
+// and textured with the baked view closest to the camera direction. The
+// IMPOSTOR_* values are filled in by Impostor::shader.
+
+let VIEWS: i32 = IMPOSTOR_VIEWS;
+let COLUMNS: i32 = IMPOSTOR_COLUMNS;
+let ROWS: i32 = IMPOSTOR_ROWS;
+
+[[group(0), binding(0)]]
+var t_diffuse: texture_2d<f32>;
+[[group(0), binding(1)]]
+var s_diffuse: sampler;
+
+[[block]]
+struct Camera {
+    view_position: vec4<f32>;
+    view_proj: mat4x4<f32>;
+};
+[[group(1), binding(0)]]
+var<uniform> camera: Camera;
+
+// the corners of the quad around its center, the uv of each corner, and the
+// center of the impostor in place of the normal
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] tex_coords: vec2<f32>;
+    [[location(2)]] normal: vec3<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(model: VertexInput) -> VertexOutput {
+    let center = model.normal;
+    let radius = length(model.position - center) * 0.70710678;
+
+    let to_camera = camera.view_position.xyz - center;
+    var forward = vec3<f32>(to_camera.x, 0.0, to_camera.z);
+    if (length(forward) < 0.00001) {
+        forward = vec3<f32>(0.0, 0.0, 1.0);
+    }
+    forward = normalize(forward);
+    let up = vec3<f32>(0.0, 1.0, 0.0);
+    let right = cross(up, forward);
+    let corner = model.tex_coords * 2.0 - 1.0;
+    let position = center + right * (corner.x * radius) - up * (corner.y * radius);
+
+    // views are baked counterclockwise from +x, seen from above
+    let angle = atan2(forward.z, forward.x);
+    var view = i32(round(angle / 6.2831853 * f32(VIEWS)));
+    view = (view % VIEWS + VIEWS) % VIEWS;
+    let cell = vec2<f32>(f32(view % COLUMNS), f32(view / COLUMNS));
+
+    var out: VertexOutput;
+    out.tex_coords = (cell + model.tex_coords) / vec2<f32>(f32(COLUMNS), f32(ROWS));
+    out.clip_position = camera.view_proj * vec4<f32>(position, 1.0);
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    if (color.a < 0.5) {
+        discard;
+    }
+    return color;
+}
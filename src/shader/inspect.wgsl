@@ -0,0 +1,54 @@
+// A texture drawn over a rectangle of the target for debugging. t_inspect and
+// load() are declared before this by inspector::TextureInspector, as a color
+// or a depth texture.
+
+[[block]]
+struct Inspect {
+    // channels shown, a single one is shown as grey
+    mask: vec4<f32>;
+    // value drawn black and value drawn white
+    range: vec2<f32>;
+    // near and far planes the depth was drawn with, zero to show it raw
+    planes: vec2<f32>;
+    grey: f32;
+};
+[[group(0), binding(1)]]
+var<uniform> inspect: Inspect;
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+};
+
+// one triangle covering the whole viewport
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    out.tex_coords = uv;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    // nearest texel, the mip and layer are the whole view
+    let size = textureDimensions(t_inspect);
+    let coords = clamp(vec2<i32>(in.tex_coords * vec2<f32>(size)), vec2<i32>(0), size - 1);
+    var value = load(coords);
+
+    // distance between the planes, from 0 at the near one to 1 at the far one
+    let near = inspect.planes.x;
+    let far = inspect.planes.y;
+    if (far > 0.0) {
+        let distance = near * far / (far - value.r * (far - near));
+        value = vec4<f32>(vec3<f32>((distance - near) / (far - near)), 1.0);
+    }
+
+    value = (value - inspect.range.x) / (inspect.range.y - inspect.range.x);
+    value = clamp(value, vec4<f32>(0.0), vec4<f32>(1.0)) * inspect.mask;
+    if (inspect.grey > 0.0) {
+        return vec4<f32>(vec3<f32>(value.r + value.g + value.b + value.a), 1.0);
+    }
+    return vec4<f32>(value.rgb, 1.0);
+}
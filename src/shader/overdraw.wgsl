@@ -0,0 +1,21 @@
+// Heatmap of the overdraw counts of a view, drawn over its whole target. The
+// palette is declared before this.
+
+// fragments per pixel shown at the bright end of the palette
+let MAX_OVERDRAW: f32 = 8.0;
+
+[[group(0), binding(0)]]
+var t_overdraw: texture_2d<f32>;
+
+// one triangle covering the whole viewport
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] index: u32) -> [[builtin(position)]] vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+[[stage(fragment)]]
+fn fs_main([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] vec4<f32> {
+    let count = textureLoad(t_overdraw, vec2<i32>(position.xy), 0).r;
+    return vec4<f32>(palette(count / MAX_OVERDRAW), 1.0);
+}
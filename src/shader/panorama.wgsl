@@ -0,0 +1,44 @@
+// Equirectangular image mapped on a unit sphere that follows the camera
+
+[[group(0), binding(0)]]
+var t_panorama: texture_2d<f32>;
+[[group(0), binding(1)]]
+var s_panorama: sampler;
+
+[[block]]
+struct Camera {
+    view_position: vec4<f32>;
+    view_proj: mat4x4<f32>;
+};
+[[group(1), binding(0)]]
+var<uniform> camera: Camera;
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] direction: vec3<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.direction = model.position;
+    let clip_position = camera.view_proj * vec4<f32>(camera.view_position.xyz + model.position, 1.0);
+    // push the sphere right in front of the far plane, behind everything else
+    out.clip_position = vec4<f32>(clip_position.xy, clip_position.w * 0.99999, clip_position.w);
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let pi = 3.14159265;
+    let direction = normalize(in.direction);
+    let tex_coords = vec2<f32>(
+        0.5 + atan2(direction.z, direction.x) / (2.0 * pi),
+        0.5 - asin(direction.y) / pi,
+    );
+    return textureSample(t_panorama, s_panorama, tex_coords);
+}
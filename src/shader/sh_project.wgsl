@@ -0,0 +1,92 @@
+// Projection of a cube map onto the first 9 spherical harmonics. Each
+// invocation sums one row of one face, the rows are added up on the CPU.
+
+struct Row {
+    // w of the first coefficient holds the solid angle of the row
+    coefficients: array<vec4<f32>, 9>;
+};
+
+[[block]]
+struct Rows {
+    rows: array<Row>;
+};
+
+[[group(0), binding(0)]]
+var faces: texture_2d_array<f32>;
+[[group(0), binding(1)]]
+var<storage, read_write> output: Rows;
+
+// Direction through the center of a texel, in the +X, -X, +Y, -Y, +Z, -Z face
+// order with v going down
+fn texel_direction(face: i32, u: f32, v: f32) -> vec3<f32> {
+    if (face == 0) {
+        return vec3<f32>(1.0, -v, -u);
+    }
+    if (face == 1) {
+        return vec3<f32>(-1.0, -v, u);
+    }
+    if (face == 2) {
+        return vec3<f32>(u, 1.0, v);
+    }
+    if (face == 3) {
+        return vec3<f32>(u, -1.0, -v);
+    }
+    if (face == 4) {
+        return vec3<f32>(u, -v, 1.0);
+    }
+    return vec3<f32>(-u, -v, -1.0);
+}
+
+[[stage(compute), workgroup_size(64)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let size = textureDimensions(faces).x;
+    let row = i32(id.x);
+    let face = i32(id.y);
+    if (row >= size || face >= 6) {
+        return;
+    }
+
+    var c0 = vec3<f32>(0.0);
+    var c1 = vec3<f32>(0.0);
+    var c2 = vec3<f32>(0.0);
+    var c3 = vec3<f32>(0.0);
+    var c4 = vec3<f32>(0.0);
+    var c5 = vec3<f32>(0.0);
+    var c6 = vec3<f32>(0.0);
+    var c7 = vec3<f32>(0.0);
+    var c8 = vec3<f32>(0.0);
+    var solid_angle = 0.0;
+
+    let texel = 2.0 / f32(size);
+    let v = (f32(row) + 0.5) * texel - 1.0;
+    for (var column = 0; column < size; column = column + 1) {
+        let u = (f32(column) + 0.5) * texel - 1.0;
+        let d = texel_direction(face, u, v);
+        let n = normalize(d);
+        // solid angle of the texel seen from the center of the cube
+        let weight = texel * texel / pow(dot(d, d), 1.5);
+        let color = textureLoad(faces, vec2<i32>(column, row), face, 0).rgb * weight;
+
+        c0 = c0 + color * 0.282095;
+        c1 = c1 + color * (0.488603 * n.y);
+        c2 = c2 + color * (0.488603 * n.z);
+        c3 = c3 + color * (0.488603 * n.x);
+        c4 = c4 + color * (1.092548 * n.x * n.y);
+        c5 = c5 + color * (1.092548 * n.y * n.z);
+        c6 = c6 + color * (0.315392 * (3.0 * n.z * n.z - 1.0));
+        c7 = c7 + color * (1.092548 * n.x * n.z);
+        c8 = c8 + color * (0.546274 * (n.x * n.x - n.y * n.y));
+        solid_angle = solid_angle + weight;
+    }
+
+    let index = face * size + row;
+    output.rows[index].coefficients[0] = vec4<f32>(c0, solid_angle);
+    output.rows[index].coefficients[1] = vec4<f32>(c1, 0.0);
+    output.rows[index].coefficients[2] = vec4<f32>(c2, 0.0);
+    output.rows[index].coefficients[3] = vec4<f32>(c3, 0.0);
+    output.rows[index].coefficients[4] = vec4<f32>(c4, 0.0);
+    output.rows[index].coefficients[5] = vec4<f32>(c5, 0.0);
+    output.rows[index].coefficients[6] = vec4<f32>(c6, 0.0);
+    output.rows[index].coefficients[7] = vec4<f32>(c7, 0.0);
+    output.rows[index].coefficients[8] = vec4<f32>(c8, 0.0);
+}
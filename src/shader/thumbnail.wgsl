@@ -0,0 +1,61 @@
+// Default shading used to render thumbnails: diffuse texture lit by a single light
+
+[[group(0), binding(0)]]
+var t_diffuse: texture_2d<f32>;
+[[group(0), binding(1)]]
+var s_diffuse: sampler;
+
+[[block]]
+struct Camera {
+    view_position: vec4<f32>;
+    view_proj: mat4x4<f32>;
+};
+[[group(1), binding(0)]]
+var<uniform> camera: Camera;
+
+[[block]]
+struct Light {
+    position: vec3<f32>;
+    color: vec3<f32>;
+    sky_color: vec3<f32>;
+    ground_color: vec3<f32>;
+};
+[[group(2), binding(0)]]
+var<uniform> light: Light;
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] tex_coords: vec2<f32>;
+    [[location(2)]] normal: vec3<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+    [[location(1)]] world_normal: vec3<f32>;
+    [[location(2)]] world_position: vec3<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.world_normal = model.normal;
+    out.world_position = model.position;
+    out.clip_position = camera.view_proj * vec4<f32>(model.position, 1.0);
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let object_color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+
+    // ambient from the sky above and the ground below
+    let normal = normalize(in.world_normal);
+    let ambient = mix(light.ground_color, light.sky_color, normal.y * 0.5 + 0.5);
+    let light_dir = normalize(light.position - in.world_position);
+    let diffuse_strength = max(dot(normal, light_dir), 0.0);
+
+    let result = (ambient + diffuse_strength * light.color) * object_color.xyz;
+    return vec4<f32>(result, object_color.a);
+}
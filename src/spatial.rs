@@ -0,0 +1,447 @@
+use crate::bounds::{Aabb, Frustum};
+use crate::camera::Ray;
+use cgmath::*;
+use std::collections::HashMap;
+
+const NONE: usize = usize::MAX;
+
+struct TreeNode {
+    // bounds of the subtree, grown by the margin around the leaves
+    bounds: Aabb,
+    parent: usize,
+    // NONE for leaves
+    children: [usize; 2],
+    // key and exact bounds of leaves
+    key: usize,
+    tight: Aabb,
+}
+
+impl TreeNode {
+    fn is_leaf(&self) -> bool {
+        self.children[0] == NONE
+    }
+}
+
+// Dynamic bounding volume tree over the nodes of a scene, keyed by node index.
+// Leaves keep a margin around their bounds so that nodes moving a little don't
+// change the tree.
+pub struct SpatialIndex {
+    nodes: Vec<TreeNode>,
+    free: Vec<usize>,
+    root: usize,
+    leaves: HashMap<usize, usize>,
+    margin: f32,
+}
+
+impl SpatialIndex {
+    pub fn new(margin: f32) -> Self {
+        Self {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            root: NONE,
+            leaves: HashMap::new(),
+            margin,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    fn allocate(&mut self, node: TreeNode) -> usize {
+        match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = node;
+                index
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    // Add a node, or move it if the key is already in the index
+    pub fn insert(&mut self, key: usize, bounds: Aabb) {
+        if self.leaves.contains_key(&key) {
+            self.update(key, bounds);
+            return;
+        }
+        let leaf = self.allocate(TreeNode {
+            bounds: bounds.expand(self.margin),
+            parent: NONE,
+            children: [NONE; 2],
+            key,
+            tight: bounds,
+        });
+        self.leaves.insert(key, leaf);
+        self.insert_leaf(leaf);
+    }
+
+    // Change the bounds of a node, the tree only changes when they leave the
+    // margin of the previous ones
+    pub fn update(&mut self, key: usize, bounds: Aabb) {
+        let leaf = match self.leaves.get(&key) {
+            Some(&leaf) => leaf,
+            None => return self.insert(key, bounds),
+        };
+        self.nodes[leaf].tight = bounds;
+        if self.nodes[leaf].bounds.contains(&bounds) {
+            return;
+        }
+        self.remove_leaf(leaf);
+        self.nodes[leaf].bounds = bounds.expand(self.margin);
+        self.insert_leaf(leaf);
+    }
+
+    // Exact bounds of a node, without the margin
+    pub fn bounds(&self, key: usize) -> Option<Aabb> {
+        self.leaves.get(&key).map(|&leaf| self.nodes[leaf].tight)
+    }
+
+    pub fn remove(&mut self, key: usize) {
+        if let Some(leaf) = self.leaves.remove(&key) {
+            self.remove_leaf(leaf);
+            self.free.push(leaf);
+        }
+    }
+
+    // Put the leaf next to the node whose bounds grow the least with it
+    fn insert_leaf(&mut self, leaf: usize) {
+        if self.root == NONE {
+            self.root = leaf;
+            self.nodes[leaf].parent = NONE;
+            return;
+        }
+
+        let bounds = self.nodes[leaf].bounds;
+        let mut sibling = self.root;
+        while !self.nodes[sibling].is_leaf() {
+            let node = &self.nodes[sibling];
+            let area = node.bounds.surface_area();
+            let combined = node.bounds.union(&bounds).surface_area();
+            // cost of a new parent here, and of the growth of this node if the
+            // leaf goes further down
+            let cost = 2.0 * combined;
+            let inherited = 2.0 * (combined - area);
+            let child_cost = |child: usize| {
+                let child = &self.nodes[child];
+                let grown = child.bounds.union(&bounds).surface_area();
+                if child.is_leaf() {
+                    grown + inherited
+                } else {
+                    grown - child.bounds.surface_area() + inherited
+                }
+            };
+            let [first, second] = node.children;
+            let (first_cost, second_cost) = (child_cost(first), child_cost(second));
+            if cost < first_cost && cost < second_cost {
+                break;
+            }
+            sibling = if first_cost < second_cost {
+                first
+            } else {
+                second
+            };
+        }
+
+        let old_parent = self.nodes[sibling].parent;
+        let parent = self.allocate(TreeNode {
+            bounds: self.nodes[sibling].bounds.union(&bounds),
+            parent: old_parent,
+            children: [sibling, leaf],
+            key: NONE,
+            tight: Aabb::empty(),
+        });
+        self.nodes[sibling].parent = parent;
+        self.nodes[leaf].parent = parent;
+        if old_parent == NONE {
+            self.root = parent;
+        } else {
+            let children = &mut self.nodes[old_parent].children;
+            let slot = usize::from(children[1] == sibling);
+            children[slot] = parent;
+        }
+        self.refit(old_parent);
+    }
+
+    // Take the leaf out of the tree, its sibling replacing their parent
+    fn remove_leaf(&mut self, leaf: usize) {
+        if leaf == self.root {
+            self.root = NONE;
+            return;
+        }
+        let parent = self.nodes[leaf].parent;
+        let grandparent = self.nodes[parent].parent;
+        let children = self.nodes[parent].children;
+        let sibling = if children[0] == leaf {
+            children[1]
+        } else {
+            children[0]
+        };
+
+        self.nodes[sibling].parent = grandparent;
+        if grandparent == NONE {
+            self.root = sibling;
+        } else {
+            let children = &mut self.nodes[grandparent].children;
+            let slot = usize::from(children[1] == parent);
+            children[slot] = sibling;
+        }
+        self.free.push(parent);
+        self.refit(grandparent);
+    }
+
+    // Recompute the bounds from the node up to the root
+    fn refit(&mut self, mut index: usize) {
+        while index != NONE {
+            let [first, second] = self.nodes[index].children;
+            self.nodes[index].bounds = self.nodes[first].bounds.union(&self.nodes[second].bounds);
+            index = self.nodes[index].parent;
+        }
+    }
+
+    // Keys of the leaves whose exact bounds pass the test, the subtrees whose
+    // bounds fail it are skipped
+    fn query<F: Fn(&Aabb) -> bool>(&self, test: F) -> Vec<usize> {
+        let mut keys = Vec::new();
+        let mut stack = vec![self.root];
+        while let Some(index) = stack.pop() {
+            let node = match self.nodes.get(index) {
+                Some(node) => node,
+                None => continue,
+            };
+            if !test(&node.bounds) {
+                continue;
+            }
+            if node.is_leaf() {
+                if test(&node.tight) {
+                    keys.push(node.key);
+                }
+            } else {
+                stack.extend(node.children);
+            }
+        }
+        keys
+    }
+
+    // Nodes that may be visible in the frustum
+    pub fn in_frustum(&self, frustum: &Frustum) -> Vec<usize> {
+        self.query(|bounds| frustum.intersects(bounds))
+    }
+
+    // Nodes with some of their bounds within the radius of the point, e.g. the
+    // lights around a position
+    pub fn within_radius(&self, center: Point3<f32>, radius: f32) -> Vec<usize> {
+        self.query(|bounds| bounds.distance(center) <= radius)
+    }
+
+    // Nodes whose bounds the ray goes through before max_distance, the closest
+    // first with the distance at which the ray enters them. Picking tests the
+    // geometry of the first ones to find the node actually hit.
+    pub fn raycast(&self, ray: &Ray, max_distance: f32) -> Vec<(usize, f32)> {
+        let mut hits: Vec<(usize, f32)> = self
+            .query(|bounds| bounds.ray_distance(ray, max_distance).is_some())
+            .into_iter()
+            .filter_map(|key| {
+                let leaf = &self.nodes[self.leaves[&key]];
+                leaf.tight
+                    .ray_distance(ray, max_distance)
+                    .map(|distance| (key, distance))
+            })
+            .collect();
+        hits.sort_by(|a, b| a.1.total_cmp(&b.1));
+        hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::OPENGL_TO_WGPU_MATRIX;
+
+    // xorshift numbers in [0, 1), the same on every run
+    fn random(state: &mut u32) -> f32 {
+        *state ^= *state << 13;
+        *state ^= *state >> 17;
+        *state ^= *state << 5;
+        (*state >> 8) as f32 / (1 << 24) as f32
+    }
+
+    // a box of up to 1 unit somewhere within 10 units of the origin
+    fn random_box(state: &mut u32) -> Aabb {
+        let min = Point3::new(random(state), random(state), random(state)) * 20.0
+            - Vector3::new(10.0, 10.0, 10.0);
+        let size = Vector3::new(random(state), random(state), random(state));
+        Aabb {
+            min,
+            max: min + size,
+        }
+    }
+
+    // every node holds the bounds below it, and the leaves are the keys
+    fn assert_tree(index: &SpatialIndex) {
+        let mut leaves = 0;
+        let mut stack: Vec<usize> = (index.root != NONE)
+            .then_some(index.root)
+            .into_iter()
+            .collect();
+        while let Some(node) = stack.pop() {
+            let tree_node = &index.nodes[node];
+            if tree_node.is_leaf() {
+                assert!(tree_node.bounds.contains(&tree_node.tight));
+                assert_eq!(index.leaves[&tree_node.key], node);
+                leaves += 1;
+                continue;
+            }
+            for child in tree_node.children {
+                assert_eq!(index.nodes[child].parent, node);
+                assert!(tree_node.bounds.contains(&index.nodes[child].bounds));
+                stack.push(child);
+            }
+        }
+        assert_eq!(leaves, index.len());
+    }
+
+    fn sorted(mut keys: Vec<usize>) -> Vec<usize> {
+        keys.sort_unstable();
+        keys
+    }
+
+    // the queries find the same nodes as a test of every box
+    fn assert_queries(index: &SpatialIndex, boxes: &HashMap<usize, Aabb>, state: &mut u32) {
+        let brute_force = |test: &dyn Fn(&Aabb) -> bool| {
+            sorted(
+                boxes
+                    .iter()
+                    .filter(|(_, b)| test(b))
+                    .map(|(k, _)| *k)
+                    .collect(),
+            )
+        };
+        // the queries don't all come back empty
+        let mut found = [0; 3];
+        let targets: Vec<Point3<f32>> = sorted(boxes.keys().copied().collect())
+            .into_iter()
+            .map(|key| boxes[&key].center())
+            .collect();
+        for i in 0..20 {
+            // looking at one of the boxes
+            let eye = random_box(state).center() * 1.5;
+            let target = targets
+                .get(i * 7 % targets.len().max(1))
+                .copied()
+                .unwrap_or_else(Point3::origin);
+            let view_proj = OPENGL_TO_WGPU_MATRIX
+                * perspective(Deg(60.0), 1.5, 0.1, 8.0)
+                * Matrix4::look_at_rh(eye, target, Vector3::unit_y());
+            let frustum = Frustum::from_view_proj(&view_proj);
+            found[0] += index.in_frustum(&frustum).len();
+            assert_eq!(
+                sorted(index.in_frustum(&frustum)),
+                brute_force(&|b| frustum.intersects(b))
+            );
+            assert_eq!(
+                sorted(index.in_frustum(&frustum.sides())),
+                brute_force(&|b| frustum.sides().intersects(b))
+            );
+
+            let radius = random(state) * 5.0;
+            found[1] += index.within_radius(eye, radius).len();
+            assert_eq!(
+                sorted(index.within_radius(eye, radius)),
+                brute_force(&|b| b.distance(eye) <= radius)
+            );
+
+            let ray = Ray {
+                origin: eye,
+                direction: (target - eye).normalize(),
+            };
+            let mut expected: Vec<(usize, f32)> = boxes
+                .iter()
+                .filter_map(|(key, b)| b.ray_distance(&ray, 15.0).map(|distance| (*key, distance)))
+                .collect();
+            expected.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+            let mut hits = index.raycast(&ray, 15.0);
+            hits.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+            found[2] += hits.len();
+            assert_eq!(hits, expected);
+        }
+        if !boxes.is_empty() {
+            assert!(found.iter().all(|found| *found > 0), "{:?}", found);
+        }
+    }
+
+    #[test]
+    fn queries_match_brute_force() {
+        let mut state = 0x1234_5678;
+        let mut index = SpatialIndex::new(0.5);
+        let mut boxes = HashMap::new();
+        for key in 0..200 {
+            let bounds = random_box(&mut state);
+            index.insert(key, bounds);
+            boxes.insert(key, bounds);
+        }
+        assert_eq!(index.len(), 200);
+        assert_tree(&index);
+        assert_queries(&index, &boxes, &mut state);
+
+        // moves within the margin keep the tree, the exact bounds still change
+        let leaf = index.leaves[&7];
+        let tree_bounds = index.nodes[leaf].bounds;
+        let nudged = Aabb {
+            min: boxes[&7].min + Vector3::new(0.2, 0.0, 0.0),
+            max: boxes[&7].max + Vector3::new(0.2, 0.0, 0.0),
+        };
+        index.update(7, nudged);
+        boxes.insert(7, nudged);
+        assert_eq!(index.nodes[leaf].bounds, tree_bounds);
+        assert_eq!(index.bounds(7), Some(nudged));
+
+        // moves past the margin, inserting an existing key moves it too
+        for key in (0..200).step_by(3) {
+            let bounds = random_box(&mut state);
+            if key % 2 == 0 {
+                index.update(key, bounds);
+            } else {
+                index.insert(key, bounds);
+            }
+            boxes.insert(key, bounds);
+        }
+        assert_eq!(index.len(), 200);
+        assert_tree(&index);
+        assert_queries(&index, &boxes, &mut state);
+
+        // removed keys are gone, their nodes are reused by the next inserts
+        for key in (0..200).step_by(2) {
+            index.remove(key);
+            boxes.remove(&key);
+        }
+        index.remove(1000);
+        assert_eq!(index.len(), 100);
+        assert_eq!(index.bounds(0), None);
+        assert_tree(&index);
+        assert_queries(&index, &boxes, &mut state);
+        let allocated = index.nodes.len();
+        for key in 200..250 {
+            let bounds = random_box(&mut state);
+            index.insert(key, bounds);
+            boxes.insert(key, bounds);
+        }
+        assert_eq!(index.nodes.len(), allocated);
+        assert_tree(&index);
+        assert_queries(&index, &boxes, &mut state);
+
+        for key in boxes.keys() {
+            index.remove(*key);
+        }
+        assert!(index.is_empty());
+        assert!(index
+            .in_frustum(&Frustum::from_view_proj(&Matrix4::identity()))
+            .is_empty());
+    }
+}
@@ -0,0 +1,97 @@
+use crate::bounds::Aabb;
+use cgmath::{Matrix4, Point3};
+
+// Counts of what a scene holds, for outliners and debugging
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SceneStats {
+    // objects of the scene, each one holding meshes
+    pub nodes: usize,
+    pub meshes: usize,
+    // materials of the objects, each one drawn with its diffuse texture
+    pub materials: usize,
+    // triangles of the geometry, instances not counted
+    pub triangles: usize,
+    // world bounds of the objects
+    pub bounds: Aabb,
+}
+
+impl Default for SceneStats {
+    fn default() -> Self {
+        Self {
+            nodes: 0,
+            meshes: 0,
+            materials: 0,
+            triangles: 0,
+            bounds: Aabb::empty(),
+        }
+    }
+}
+
+impl SceneStats {
+    // Add the meshes of a loaded object as one more node, placed in the world
+    // by the transform
+    pub fn add_object(&mut self, object: &crate::Object, transform: &Matrix4<f32>) {
+        self.nodes += 1;
+        self.meshes += object.models.len();
+        // the loader makes a material of each diffuse texture
+        self.materials += object
+            .textures
+            .as_ref()
+            .map_or(0, |textures| textures.len());
+        for model in &object.models {
+            self.triangles += model.mesh.indices.len() / 3;
+            let positions = model.mesh.positions.chunks_exact(3);
+            let bounds = Aabb::from_points(positions.map(|p| Point3::new(p[0], p[1], p[2])));
+            self.bounds = self.bounds.union(&bounds.transform(transform));
+        }
+    }
+}
+
+// Entry of the scene hierarchy: objects at depth 0, followed by their meshes
+// at depth 1
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeInfo<'a> {
+    pub name: &'a str,
+    pub depth: usize,
+    pub transform: cgmath::Matrix4<f32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{SquareMatrix, Vector3};
+
+    fn triangle_object() -> crate::Object {
+        let mesh = tobj::Mesh {
+            positions: vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+            indices: vec![0, 1, 2],
+            ..Default::default()
+        };
+        crate::Object {
+            models: vec![
+                tobj::Model::new(mesh.clone(), "a".into()),
+                tobj::Model::new(mesh, "b".into()),
+            ],
+            textures: None,
+            sanitize_report: Default::default(),
+        }
+    }
+
+    #[test]
+    fn counts_and_world_bounds() {
+        let mut stats = SceneStats::default();
+        assert!(stats.bounds.is_empty());
+        stats.add_object(&triangle_object(), &Matrix4::identity());
+        stats.add_object(
+            &triangle_object(),
+            &Matrix4::from_translation(Vector3::new(10.0, 0.0, -2.0)),
+        );
+        assert_eq!(stats.nodes, 2);
+        assert_eq!(stats.meshes, 4);
+        assert_eq!(stats.materials, 0);
+        assert_eq!(stats.triangles, 4);
+        // the moved object is where its transform puts it
+        assert_eq!(stats.bounds.min, Point3::new(0.0, 0.0, -2.0));
+        assert_eq!(stats.bounds.max, Point3::new(11.0, 1.0, 0.0));
+    }
+}
@@ -6,6 +6,7 @@ pub struct Texture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
     pub sampler: wgpu::Sampler,
+    pub size: wgpu::Extent3d,
 }
 
 impl Texture {
@@ -26,8 +27,17 @@ impl Texture {
         label: Option<&str>,
     ) -> Result<Self> {
         let rgba = img.to_rgba8();
-        let dimensions = img.dimensions();
+        Self::from_rgba8(device, queue, &rgba, img.dimensions(), label)
+    }
 
+    // Import raw 8 bits rgba pixels, e.g. a frame decoded outside of lens
+    pub fn from_rgba8(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        rgba: &[u8],
+        dimensions: (u32, u32),
+        label: Option<&str>,
+    ) -> Result<Self> {
         let size = wgpu::Extent3d {
             width: dimensions.0,
             height: dimensions.1,
@@ -43,29 +53,124 @@ impl Texture {
             usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
         });
 
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let texture = Self {
+            texture,
+            view,
+            sampler,
+            size,
+        };
+        texture.write_rgba8(queue, rgba)?;
+
+        Ok(texture)
+    }
+
+    // Replace the content of the texture with new pixels of the same size. Views
+    // and bind groups using the texture keep pointing to it, so a material can
+    // show a new frame each time it is drawn.
+    pub fn write_rgba8(&self, queue: &wgpu::Queue, rgba: &[u8]) -> Result<()> {
+        ensure!(
+            rgba.len() == (4 * self.size.width * self.size.height) as usize,
+            "expected {}x{} rgba pixels, got {} bytes",
+            self.size.width,
+            self.size.height,
+            rgba.len()
+        );
+
         queue.write_texture(
             wgpu::ImageCopyTexture {
                 aspect: wgpu::TextureAspect::All,
-                texture: &texture,
+                texture: &self.texture,
                 mip_level: 0,
                 origin: wgpu::Origin3d::ZERO,
             },
-            &rgba,
+            rgba,
             wgpu::ImageDataLayout {
                 offset: 0,
-                bytes_per_row: std::num::NonZeroU32::new(4 * dimensions.0),
-                rows_per_image: std::num::NonZeroU32::new(dimensions.1),
+                bytes_per_row: std::num::NonZeroU32::new(4 * self.size.width),
+                rows_per_image: std::num::NonZeroU32::new(self.size.height),
             },
-            size,
+            self.size,
         );
 
-        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Ok(())
+    }
+
+    // Cube texture from its faces of the same size, in the +X, -X, +Y, -Y, +Z,
+    // -Z order
+    pub fn cube_from_images(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        faces: &[image::DynamicImage; 6],
+        label: Option<&str>,
+    ) -> Result<Self> {
+        let (width, height) = faces[0].dimensions();
+        ensure!(
+            faces
+                .iter()
+                .all(|face| face.dimensions() == (width, height)),
+            "cube faces don't have the same size"
+        );
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 6,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label,
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+
+        for (layer, face) in faces.iter().enumerate() {
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    aspect: wgpu::TextureAspect::All,
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer as u32,
+                    },
+                },
+                &face.to_rgba8(),
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(4 * width),
+                    rows_per_image: std::num::NonZeroU32::new(height),
+                },
+                wgpu::Extent3d {
+                    depth_or_array_layers: 1,
+                    ..size
+                },
+            );
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
             address_mode_w: wgpu::AddressMode::ClampToEdge,
             mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Linear,
             mipmap_filter: wgpu::FilterMode::Nearest,
             ..Default::default()
         });
@@ -74,6 +179,7 @@ impl Texture {
             texture,
             view,
             sampler,
+            size,
         })
     }
 
@@ -92,10 +198,35 @@ impl Texture {
 
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 
+    // Formats depth targets can be created with, the adapter may not support
+    // all of them
+    pub const DEPTH_FORMATS: [wgpu::TextureFormat; 3] = [
+        wgpu::TextureFormat::Depth32Float,
+        wgpu::TextureFormat::Depth24Plus,
+        wgpu::TextureFormat::Depth24PlusStencil8,
+    ];
+
+    // Clear of the stencil at the start of a pass, for the formats with one
+    pub fn stencil_ops(format: wgpu::TextureFormat) -> Option<wgpu::Operations<u32>> {
+        (format == wgpu::TextureFormat::Depth24PlusStencil8).then_some(wgpu::Operations {
+            load: wgpu::LoadOp::Clear(0),
+            store: true,
+        })
+    }
+
     pub fn create_depth_texture(
         device: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration,
         label: &str,
+    ) -> Self {
+        Self::create_depth_texture_with_format(device, config, Self::DEPTH_FORMAT, label)
+    }
+
+    pub fn create_depth_texture_with_format(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        format: wgpu::TextureFormat,
+        label: &str,
     ) -> Self {
         let size = wgpu::Extent3d {
             width: config.width,
@@ -108,7 +239,7 @@ impl Texture {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: Self::DEPTH_FORMAT,
+            format,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
         };
         let texture = device.create_texture(&desc);
@@ -131,6 +262,7 @@ impl Texture {
             texture,
             view,
             sampler,
+            size,
         }
     }
 }
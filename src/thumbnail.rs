@@ -0,0 +1,340 @@
+use crate::renderer::{Material, Model, ModelRenderer, ModelVertex, Vertex};
+use crate::{binding, camera, light, texture};
+use anyhow::*;
+use cgmath::{Matrix3, Matrix4, Point3, Vector3};
+use wgpu::util::DeviceExt;
+
+// Format of the thumbnail target, read back as 8 bits rgba pixels
+const THUMBNAIL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+// Neutral grey behind the model
+const BACKGROUND_COLOR: wgpu::Color = wgpu::Color {
+    r: 0.2,
+    g: 0.2,
+    b: 0.2,
+    a: 1.0,
+};
+
+// View of a model rendered offscreen, drawn to an area of the image
+#[derive(Copy, Clone)]
+pub(crate) struct OffscreenView {
+    pub position: Point3<f32>,
+    pub view_proj: Matrix4<f32>,
+    pub viewport: camera::Viewport,
+}
+
+fn surface_config(size: winit::dpi::PhysicalSize<u32>) -> wgpu::SurfaceConfiguration {
+    wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        format: THUMBNAIL_FORMAT,
+        width: size.width,
+        height: size.height,
+        present_mode: wgpu::PresentMode::Fifo,
+    }
+}
+
+// Render a model framed by the default camera into an image, without any window
+pub fn render_thumbnail(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    model: &Model,
+    size: winit::dpi::PhysicalSize<u32>,
+) -> Result<image::RgbaImage> {
+    ensure!(
+        size.width > 0 && size.height > 0,
+        "thumbnail size must not be zero"
+    );
+    ensure!(!model.bounds.is_empty(), "model has no vertices to render");
+
+    // look at the model from the default camera direction
+    let mut camera_binder = camera::Camera::new(device, &surface_config(size));
+    camera_binder.framing_transition = None;
+    camera_binder.frame_model(model);
+
+    render_views(
+        device,
+        queue,
+        model,
+        size,
+        &[OffscreenView {
+            position: camera_binder.position(),
+            view_proj: camera_binder.view_proj(),
+            viewport: camera::Viewport::from_size(size),
+        }],
+        BACKGROUND_COLOR,
+    )
+}
+
+// Render a model turning around its vertical axis, one image per frame, e.g.
+// for an asset preview or the frames of a GIF. It starts from the thumbnail
+// view and makes a full turn over the frames.
+pub fn render_turntable(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    model: &Model,
+    size: winit::dpi::PhysicalSize<u32>,
+    frames: usize,
+) -> Result<Vec<image::RgbaImage>> {
+    ensure!(
+        size.width > 0 && size.height > 0,
+        "turntable size must not be zero"
+    );
+    ensure!(frames > 0, "turntable needs at least one frame");
+    ensure!(!model.bounds.is_empty(), "model has no vertices to render");
+
+    let mut camera_binder = camera::Camera::new(device, &surface_config(size));
+    camera_binder.framing_transition = None;
+    camera_binder.frame_model(model);
+    let center = model.bounds.center();
+    let offset = camera_binder.position() - center;
+    let projection = camera_binder.projection.calc_matrix();
+
+    let frames: Vec<Vec<OffscreenView>> = (0..frames)
+        .map(|frame| {
+            let angle = cgmath::Deg(360.0 * frame as f32 / frames as f32);
+            let position = center + Matrix3::from_angle_y(angle) * offset;
+            let view = Matrix4::look_at_rh(position, center, Vector3::unit_y());
+            vec![OffscreenView {
+                position,
+                view_proj: projection * view,
+                viewport: camera::Viewport::from_size(size),
+            }]
+        })
+        .collect();
+    render_frames(device, queue, model, size, &frames, BACKGROUND_COLOR)
+}
+
+// Render the views of a model into an image cleared to the given color, with
+// the default shading and a light above the model
+pub(crate) fn render_views(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    model: &Model,
+    size: winit::dpi::PhysicalSize<u32>,
+    views: &[OffscreenView],
+    clear_color: wgpu::Color,
+) -> Result<image::RgbaImage> {
+    let mut images = render_frames(device, queue, model, size, &[views.to_vec()], clear_color)?;
+    Ok(images.remove(0))
+}
+
+// Render the views of each frame into an image of its own, sharing the
+// pipeline and targets between the frames
+fn render_frames(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    model: &Model,
+    size: winit::dpi::PhysicalSize<u32>,
+    frames: &[Vec<OffscreenView>],
+    clear_color: wgpu::Color,
+) -> Result<Vec<image::RgbaImage>> {
+    let config = surface_config(size);
+    let camera_layout = camera::Camera::create_layout(device);
+
+    // a single white light, above the model on the camera side
+    let light_position = model.bounds.center()
+        + cgmath::Vector3::new(1.0, 2.0, 2.0) * model.bounds.radius().max(1.0);
+    let (sky_color, ground_color) = light::flat_ambient([1.0, 1.0, 1.0]);
+    let light_binder = light::Light::bind(
+        device,
+        light::LightUniform {
+            position: light_position.into(),
+            _padding: 0,
+            color: [1.0, 1.0, 1.0],
+            _padding2: 0,
+            sky_color,
+            _padding3: 0,
+            ground_color,
+            _padding4: 0,
+        },
+    );
+
+    // meshes without material are drawn with a plain white texture
+    let fallback_layout;
+    let material_layout = match model.material_layout.as_ref() {
+        Some(material_layout) => material_layout,
+        None => {
+            fallback_layout = Material::create_layout(device);
+            &fallback_layout
+        }
+    };
+    let white_image = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+        1,
+        1,
+        image::Rgba([255, 255, 255, 255]),
+    ));
+    let white_material = Material::new(
+        device,
+        material_layout,
+        "thumbnail_white".into(),
+        texture::Texture::from_image(device, queue, &white_image, Some("thumbnail_white"))?,
+    );
+
+    let render_pipeline_layout = binding::BindingRegistry::new(device).pipeline_layout(
+        device,
+        Some("Thumbnail Pipeline Layout"),
+        [
+            Some(material_layout),
+            Some(&camera_layout),
+            Some(&light_binder.bind_group_layout),
+            None,
+        ],
+    );
+    let render_pipeline = ModelRenderer::create_render_pipeline(
+        device,
+        &render_pipeline_layout,
+        THUMBNAIL_FORMAT,
+        Some(texture::Texture::DEPTH_FORMAT),
+        &[ModelVertex::desc()],
+        wgpu::ShaderModuleDescriptor {
+            label: Some("Thumbnail Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shader/thumbnail.wgsl").into()),
+        },
+    );
+
+    let extent = wgpu::Extent3d {
+        width: size.width,
+        height: size.height,
+        depth_or_array_layers: 1,
+    };
+    let target = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("thumbnail_target"),
+        size: extent,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: THUMBNAIL_FORMAT,
+        usage: config.usage,
+    });
+    let target_view = target.create_view(&wgpu::TextureViewDescriptor::default());
+    let depth_texture = texture::Texture::create_depth_texture(device, &config, "thumbnail_depth");
+
+    // rows copied out of a texture must be aligned on COPY_BYTES_PER_ROW_ALIGNMENT
+    let unpadded_bytes_per_row = 4 * size.width;
+    let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+        * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Thumbnail Buffer"),
+        size: (padded_bytes_per_row * size.height) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    frames
+        .iter()
+        .map(|views| {
+            let camera_bind_groups: Vec<wgpu::BindGroup> = views
+                .iter()
+                .map(|view| {
+                    let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("Offscreen Camera Buffer"),
+                        contents: bytemuck::cast_slice(&[camera::CameraUniform::from_view(
+                            view.position,
+                            view.view_proj,
+                        )]),
+                        usage: wgpu::BufferUsages::UNIFORM,
+                    });
+                    device.create_bind_group(&wgpu::BindGroupDescriptor {
+                        layout: &camera_layout,
+                        entries: &[wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: buffer.as_entire_binding(),
+                        }],
+                        label: Some("offscreen_camera_bind_group"),
+                    })
+                })
+                .collect();
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Thumbnail Encoder"),
+            });
+            {
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("Thumbnail Pass"),
+                    color_attachments: &[wgpu::RenderPassColorAttachment {
+                        view: &target_view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(clear_color),
+                            store: true,
+                        },
+                    }],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: &depth_texture.view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.0),
+                            store: true,
+                        }),
+                        stencil_ops: None,
+                    }),
+                });
+
+                render_pass.set_pipeline(&render_pipeline);
+                render_pass.set_bind_group(binding::LIGHT_GROUP, &light_binder.bind_group, &[]);
+                for (view, camera_bind_group) in views.iter().zip(&camera_bind_groups) {
+                    let viewport = view.viewport;
+                    render_pass.set_viewport(
+                        viewport.x,
+                        viewport.y,
+                        viewport.width,
+                        viewport.height,
+                        0.0,
+                        1.0,
+                    );
+                    render_pass.set_bind_group(binding::CAMERA_GROUP, camera_bind_group, &[]);
+                    for mesh in &model.meshes {
+                        let material = mesh
+                            .material_id
+                            .and_then(|material_id| model.materials.as_ref()?.get(material_id))
+                            .unwrap_or(&white_material);
+                        render_pass.set_bind_group(
+                            binding::MATERIAL_GROUP,
+                            &material.bind_group,
+                            &[],
+                        );
+                        render_pass.set_vertex_buffer(0, mesh.geometry.vertex_buffer.slice(..));
+                        render_pass.set_index_buffer(
+                            mesh.geometry.index_buffer.slice(..),
+                            wgpu::IndexFormat::Uint32,
+                        );
+                        render_pass.draw_indexed(0..mesh.geometry.num_elements, 0, 0..1);
+                    }
+                }
+            }
+            encoder.copy_texture_to_buffer(
+                wgpu::ImageCopyTexture {
+                    aspect: wgpu::TextureAspect::All,
+                    texture: &target,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                wgpu::ImageCopyBuffer {
+                    buffer: &output_buffer,
+                    layout: wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row),
+                        rows_per_image: std::num::NonZeroU32::new(size.height),
+                    },
+                },
+                extent,
+            );
+            queue.submit(std::iter::once(encoder.finish()));
+
+            // wait for the GPU to be done with the copy before reading the pixels
+            let buffer_slice = output_buffer.slice(..);
+            let mapping = buffer_slice.map_async(wgpu::MapMode::Read);
+            device.poll(wgpu::Maintain::Wait);
+            pollster::block_on(mapping)?;
+
+            let pixels = buffer_slice
+                .get_mapped_range()
+                .chunks(padded_bytes_per_row as usize)
+                .flat_map(|row| &row[..unpadded_bytes_per_row as usize])
+                .copied()
+                .collect();
+            output_buffer.unmap();
+
+            image::RgbaImage::from_raw(size.width, size.height, pixels)
+                .ok_or_else(|| anyhow!("thumbnail buffer does not match its size"))
+        })
+        .collect()
+}
@@ -0,0 +1,434 @@
+use cgmath::*;
+use std::collections::{HashMap, VecDeque};
+
+#[derive(Debug, Copy, Clone)]
+pub struct UnwrapOptions {
+    // size in texels of the square texture the uvs are laid out for
+    pub resolution: u32,
+    // texels kept free around each chart, so that filtering doesn't bleed
+    pub padding: u32,
+}
+
+impl Default for UnwrapOptions {
+    fn default() -> Self {
+        Self {
+            resolution: 1024,
+            padding: 2,
+        }
+    }
+}
+
+// Mesh with a second uv set laid out without overlaps in the unit square, e.g.
+// for lightmaps. Vertices on the border of two charts are split.
+pub struct UvAtlas {
+    pub mesh: tobj::Mesh,
+    // two floats per vertex of the mesh
+    pub lightmap_uvs: Vec<f32>,
+    pub charts: usize,
+}
+
+// Chart of the atlas: connected triangles facing the same side of the box
+// they are projected on, which don't overlap once projected
+struct Chart {
+    triangles: Vec<usize>,
+    // projected coordinates per original vertex, in scene units
+    coords: HashMap<u32, Vector2<f32>>,
+    min: Vector2<f32>,
+    size: Vector2<f32>,
+    offset: Vector2<f32>,
+}
+
+// Whether two projected triangles overlap by more than the tolerance, so that
+// the ones sharing an edge or a corner don't
+fn overlap(a: &[Vector2<f32>; 3], b: &[Vector2<f32>; 3], tolerance: f32) -> bool {
+    // separated along the normal of one of their edges
+    for triangle in [a, b] {
+        for k in 0..3 {
+            let edge = triangle[(k + 1) % 3] - triangle[k];
+            let axis = Vector2::new(-edge.y, edge.x);
+            let project = |triangle: &[Vector2<f32>; 3]| {
+                triangle
+                    .iter()
+                    .map(|p| p.dot(axis))
+                    .fold((f32::MAX, f32::MIN), |(low, high), d| {
+                        (low.min(d), high.max(d))
+                    })
+            };
+            let ((a_low, a_high), (b_low, b_high)) = (project(a), project(b));
+            let tolerance = tolerance * axis.magnitude();
+            if a_high <= b_low + tolerance || b_high <= a_low + tolerance {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+// Cells of a grid covered by the bounding box of a projected triangle
+fn cells(triangle: &[Vector2<f32>; 3], cell: f32) -> impl Iterator<Item = (i32, i32)> {
+    let min = |axis: usize| triangle.iter().map(|p| p[axis]).fold(f32::MAX, f32::min);
+    let max = |axis: usize| triangle.iter().map(|p| p[axis]).fold(f32::MIN, f32::max);
+    let (x0, x1) = (
+        (min(0) / cell).floor() as i32,
+        (max(0) / cell).floor() as i32,
+    );
+    let (y0, y1) = (
+        (min(1) / cell).floor() as i32,
+        (max(1) / cell).floor() as i32,
+    );
+    (x0..=x1).flat_map(move |x| (y0..=y1).map(move |y| (x, y)))
+}
+
+// Place the charts on shelves of a square, given the space kept around each one,
+// and return the side of the square
+fn pack(charts: &mut [Chart], padding: f32) -> f32 {
+    let area: f32 = charts
+        .iter()
+        .map(|chart| (chart.size.x + padding * 2.0) * (chart.size.y + padding * 2.0))
+        .sum();
+    let widest = charts
+        .iter()
+        .map(|chart| chart.size.x + padding * 2.0)
+        .fold(0.0, f32::max);
+    let shelf_width = area.sqrt().max(widest);
+
+    // tallest charts first, so that shelves waste little height
+    let mut order: Vec<usize> = (0..charts.len()).collect();
+    order.sort_by(|&a, &b| charts[b].size.y.total_cmp(&charts[a].size.y));
+    let (mut x, mut y, mut shelf_height, mut width) = (0.0f32, 0.0f32, 0.0f32, 0.0f32);
+    for i in order {
+        let chart = &mut charts[i];
+        let size = chart.size + Vector2::new(padding, padding) * 2.0;
+        if x > 0.0 && x + size.x > shelf_width {
+            x = 0.0;
+            y += shelf_height;
+            shelf_height = 0.0;
+        }
+        chart.offset = Vector2::new(x + padding, y + padding);
+        x += size.x;
+        width = width.max(x);
+        shelf_height = shelf_height.max(size.y);
+    }
+    width.max(y + shelf_height)
+}
+
+// Generate lightmap uvs for a mesh of triangles: the triangles are grouped in
+// charts of the same main normal axis, projected along that axis and packed with
+// a uniform texel density. Charts are split where their triangles would overlap. Meshes without uvs get the lightmap ones as texture
+// coordinates too.
+pub fn unwrap_uvs(mesh: &tobj::Mesh, options: UnwrapOptions) -> UvAtlas {
+    let position = |i: u32| {
+        let p = &mesh.positions[i as usize * 3..i as usize * 3 + 3];
+        Vector3::new(p[0], p[1], p[2])
+    };
+    let triangles: Vec<[u32; 3]> = mesh
+        .indices
+        .chunks_exact(3)
+        .map(|t| [t[0], t[1], t[2]])
+        .collect();
+
+    // side of the box each triangle faces: axis * 2 + 1 when facing the negative side
+    let sides: Vec<usize> = triangles
+        .iter()
+        .map(|t| {
+            let normal = (position(t[1]) - position(t[0])).cross(position(t[2]) - position(t[0]));
+            let axis = (0..3)
+                .max_by(|&a, &b| normal[a].abs().total_cmp(&normal[b].abs()))
+                .unwrap();
+            axis * 2 + usize::from(normal[axis] < 0.0)
+        })
+        .collect();
+
+    // project along the side axis, mirrored on negative sides so that all charts
+    // have the same winding
+    let projected: Vec<[Vector2<f32>; 3]> = triangles
+        .iter()
+        .zip(&sides)
+        .map(|(t, side)| {
+            let (axis, negative) = (side / 2, side % 2 == 1);
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            t.map(|i| {
+                let p = position(i);
+                Vector2::new(if negative { -p[u] } else { p[u] }, p[v])
+            })
+        })
+        .collect();
+
+    // cells of the grid finding the triangles of a chart close to another one,
+    // about the size of a triangle
+    let extents: f32 = projected
+        .iter()
+        .map(|t| {
+            let extent = |axis: usize| {
+                let values = t.map(|p| p[axis]);
+                values.iter().fold(f32::MIN, |a, &b| a.max(b))
+                    - values.iter().fold(f32::MAX, |a, &b| a.min(b))
+            };
+            extent(0).max(extent(1))
+        })
+        .sum();
+    let cell = match extents / triangles.len().max(1) as f32 {
+        cell if cell > 0.0 => cell,
+        _ => 1.0,
+    };
+    let tolerance = cell * 1e-4;
+
+    let mut edges: HashMap<(u32, u32), Vec<usize>> = HashMap::new();
+    for (i, t) in triangles.iter().enumerate() {
+        for k in 0..3 {
+            let (a, b) = (t[k], t[(k + 1) % 3]);
+            edges.entry((a.min(b), a.max(b))).or_default().push(i);
+        }
+    }
+
+    // grow each chart across the edges to the triangles of the same side, the
+    // ones overlapping the chart, e.g. on a spiral ramp, start another one
+    let mut chart_of = vec![usize::MAX; triangles.len()];
+    let mut charts: Vec<Chart> = Vec::new();
+    for seed in 0..triangles.len() {
+        if chart_of[seed] != usize::MAX {
+            continue;
+        }
+        let c = charts.len();
+        let mut chart = Chart {
+            triangles: Vec::new(),
+            coords: HashMap::new(),
+            min: Vector2::zero(),
+            size: Vector2::zero(),
+            offset: Vector2::zero(),
+        };
+        // projected triangles of the chart by the cells they cover
+        let mut grid: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+        let mut queue = VecDeque::from([seed]);
+        chart_of[seed] = c;
+        for key in cells(&projected[seed], cell) {
+            grid.entry(key).or_default().push(seed);
+        }
+        while let Some(t) = queue.pop_front() {
+            chart.triangles.push(t);
+            for k in 0..3 {
+                let (a, b) = (triangles[t][k], triangles[t][(k + 1) % 3]);
+                for &other in &edges[&(a.min(b), a.max(b))] {
+                    if chart_of[other] != usize::MAX || sides[other] != sides[t] {
+                        continue;
+                    }
+                    let overlaps = cells(&projected[other], cell).any(|key| {
+                        grid.get(&key).is_some_and(|near| {
+                            near.iter().any(|&near| {
+                                overlap(&projected[near], &projected[other], tolerance)
+                            })
+                        })
+                    });
+                    if !overlaps {
+                        chart_of[other] = c;
+                        for key in cells(&projected[other], cell) {
+                            grid.entry(key).or_default().push(other);
+                        }
+                        queue.push_back(other);
+                    }
+                }
+            }
+        }
+
+        let mut min = Vector2::new(f32::MAX, f32::MAX);
+        let mut max = Vector2::new(f32::MIN, f32::MIN);
+        for &t in &chart.triangles {
+            for (&i, &coord) in triangles[t].iter().zip(&projected[t]) {
+                min = Vector2::new(min.x.min(coord.x), min.y.min(coord.y));
+                max = Vector2::new(max.x.max(coord.x), max.y.max(coord.y));
+                chart.coords.insert(i, coord);
+            }
+        }
+        chart.min = min;
+        chart.size = max - min;
+        charts.push(chart);
+    }
+
+    // the padding is in texels, so it depends on the final scale: grow it until
+    // the packed square keeps enough space
+    let texel = 1.0 / options.resolution.max(1) as f32;
+    let mut padding = 0.0;
+    let mut side = pack(&mut charts, padding);
+    for _ in 0..8 {
+        let needed = options.padding as f32 * texel * side;
+        if padding >= needed {
+            break;
+        }
+        padding = needed * 1.05;
+        side = pack(&mut charts, padding);
+    }
+    let scale = if side > 0.0 { 1.0 / side } else { 0.0 };
+
+    // split the vertices shared by several charts
+    let mut unwrapped = tobj::Mesh {
+        material_id: mesh.material_id,
+        ..Default::default()
+    };
+    let mut lightmap_uvs = Vec::new();
+    let mut new_index = HashMap::new();
+    let mut indices = vec![0; mesh.indices.len()];
+    for (c, chart) in charts.iter().enumerate() {
+        for &t in &chart.triangles {
+            for (k, &i) in triangles[t].iter().enumerate() {
+                indices[t * 3 + k] = *new_index.entry((c, i)).or_insert_with(|| {
+                    let i = i as usize;
+                    unwrapped
+                        .positions
+                        .extend_from_slice(&mesh.positions[i * 3..i * 3 + 3]);
+                    if mesh.normals.len() >= i * 3 + 3 {
+                        unwrapped
+                            .normals
+                            .extend_from_slice(&mesh.normals[i * 3..i * 3 + 3]);
+                    }
+                    if mesh.texcoords.len() >= i * 2 + 2 {
+                        unwrapped
+                            .texcoords
+                            .extend_from_slice(&mesh.texcoords[i * 2..i * 2 + 2]);
+                    }
+                    let uv = (chart.coords[&(i as u32)] - chart.min + chart.offset) * scale;
+                    // v goes down in textures
+                    lightmap_uvs.extend_from_slice(&[uv.x, 1.0 - uv.y]);
+                    (lightmap_uvs.len() / 2 - 1) as u32
+                });
+            }
+        }
+    }
+    unwrapped.indices = indices;
+    if mesh.texcoords.is_empty() {
+        unwrapped.texcoords = lightmap_uvs.clone();
+    }
+
+    UvAtlas {
+        mesh: unwrapped,
+        lightmap_uvs,
+        charts: charts.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mesh(positions: Vec<[f32; 3]>, indices: Vec<u32>) -> tobj::Mesh {
+        tobj::Mesh {
+            positions: positions.concat(),
+            indices,
+            ..Default::default()
+        }
+    }
+
+    // the 12 triangles of a cube from -1 to 1, vertex i at the signs of its bits
+    fn cube() -> tobj::Mesh {
+        let positions = (0..8)
+            .map(|i| [0, 1, 2].map(|bit| if i & (1 << bit) != 0 { 1.0 } else { -1.0 }))
+            .collect();
+        let faces = [
+            [0, 2, 6, 4],
+            [1, 5, 7, 3],
+            [0, 4, 5, 1],
+            [2, 3, 7, 6],
+            [0, 1, 3, 2],
+            [4, 6, 7, 5],
+        ];
+        let indices = faces
+            .iter()
+            .flat_map(|[a, b, c, d]| [*a, *b, *c, *a, *c, *d])
+            .collect();
+        mesh(positions, indices)
+    }
+
+    // a ramp facing up turning one and a half times around the vertical axis,
+    // which covers itself once projected
+    fn spiral_ramp() -> tobj::Mesh {
+        let steps = 48;
+        let mut positions = Vec::new();
+        for i in 0..=steps {
+            let angle = i as f32 / steps as f32 * std::f32::consts::PI * 3.0;
+            let (sin, cos) = angle.sin_cos();
+            let height = angle * 0.05;
+            positions.push([cos, height, sin]);
+            positions.push([cos * 2.0, height, sin * 2.0]);
+        }
+        let indices = (0..steps)
+            .flat_map(|i| {
+                let (inner, outer) = (i * 2, i * 2 + 1);
+                [inner, inner + 2, outer, outer, inner + 2, outer + 2]
+            })
+            .collect();
+        mesh(positions, indices)
+    }
+
+    // every triangle of the atlas lies in the unit square, and no two overlap
+    fn assert_atlas(atlas: &UvAtlas) {
+        let uv = |i: u32| {
+            let uv = &atlas.lightmap_uvs[i as usize * 2..i as usize * 2 + 2];
+            Vector2::new(uv[0], uv[1])
+        };
+        let triangles: Vec<[Vector2<f32>; 3]> = atlas
+            .mesh
+            .indices
+            .chunks_exact(3)
+            .map(|t| [uv(t[0]), uv(t[1]), uv(t[2])])
+            .collect();
+        for triangle in &triangles {
+            for p in triangle {
+                assert!(
+                    (0.0..=1.0).contains(&p.x) && (0.0..=1.0).contains(&p.y),
+                    "{:?}",
+                    p
+                );
+            }
+        }
+        for (i, a) in triangles.iter().enumerate() {
+            for b in &triangles[i + 1..] {
+                assert!(!overlap(a, b, 1e-5), "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn cube_charts() {
+        let atlas = unwrap_uvs(&cube(), UnwrapOptions::default());
+        assert_eq!(atlas.charts, 6);
+        // the corners are split between the three faces meeting there
+        assert_eq!(atlas.mesh.positions.len(), 6 * 4 * 3);
+        assert_eq!(atlas.mesh.texcoords, atlas.lightmap_uvs);
+        assert_atlas(&atlas);
+    }
+
+    #[test]
+    fn spiral_ramp_splits_overlapping_charts() {
+        let atlas = unwrap_uvs(&spiral_ramp(), UnwrapOptions::default());
+        // at least one chart for each turn
+        assert!(atlas.charts >= 2, "{} charts", atlas.charts);
+        assert_eq!(atlas.mesh.indices.len(), 48 * 6);
+        assert_atlas(&atlas);
+    }
+
+    #[test]
+    fn overlapping_triangles() {
+        let triangle = |points: [[f32; 2]; 3]| points.map(|[x, y]| Vector2::new(x, y));
+        let a = triangle([[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]]);
+        // sharing an edge, a corner, or apart
+        assert!(!overlap(
+            &a,
+            &triangle([[1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]),
+            1e-5
+        ));
+        assert!(!overlap(
+            &a,
+            &triangle([[1.0, 0.0], [2.0, 0.0], [2.0, 1.0]]),
+            1e-5
+        ));
+        assert!(!overlap(
+            &a,
+            &triangle([[2.0, 2.0], [3.0, 2.0], [2.0, 3.0]]),
+            1e-5
+        ));
+        assert!(overlap(
+            &a,
+            &triangle([[0.2, 0.2], [1.0, 0.2], [0.2, 1.0]]),
+            1e-5
+        ));
+    }
+}
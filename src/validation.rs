@@ -0,0 +1,187 @@
+use crate::{bounds, jobs};
+use cgmath::*;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+// Models larger than this on any axis are most likely exported in the wrong unit
+pub const HUGE_BOUNDS_SIZE: f32 = 1000.0;
+
+// Triangles with a smaller area are considered degenerate
+pub const DEGENERATE_AREA: f32 = 1e-12;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    LoadFailed(String),
+    MaterialsFailed(String),
+    MissingTexture { material: String, path: PathBuf },
+    NoNormals { mesh: String },
+    NoTexCoords { mesh: String },
+    DegenerateTriangles { mesh: String, count: usize },
+    HugeBounds { size: Vector3<f32> },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::LoadFailed(error) => write!(f, "failed to load: {}", error),
+            Problem::MaterialsFailed(error) => write!(f, "failed to load materials: {}", error),
+            Problem::MissingTexture { material, path } => write!(
+                f,
+                "material {} references a missing texture {:?}",
+                material, path
+            ),
+            Problem::NoNormals { mesh } => write!(f, "mesh {} has no normals", mesh),
+            Problem::NoTexCoords { mesh } => {
+                write!(f, "mesh {} has no texture coordinates", mesh)
+            }
+            Problem::DegenerateTriangles { mesh, count } => {
+                write!(f, "mesh {} has {} degenerate triangles", mesh, count)
+            }
+            Problem::HugeBounds { size } => write!(
+                f,
+                "model bounds are huge ({} x {} x {})",
+                size.x, size.y, size.z
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AssetReport {
+    pub path: PathBuf,
+    pub problems: Vec<Problem>,
+}
+
+impl AssetReport {
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+pub fn is_degenerate_triangle(a: Point3<f32>, b: Point3<f32>, c: Point3<f32>) -> bool {
+    let area = (b - a).cross(c - a).magnitude() * 0.5;
+    // a NaN area is degenerate too
+    area.is_nan() || area <= DEGENERATE_AREA
+}
+
+// Check a single obj file, without touching the GPU
+pub fn validate_file<P: AsRef<Path>>(path: P) -> AssetReport {
+    let path = path.as_ref();
+    let mut problems = Vec::new();
+
+    let (obj_models, obj_materials) = match tobj::load_obj(
+        path,
+        &tobj::LoadOptions {
+            triangulate: true,
+            single_index: true,
+            ..Default::default()
+        },
+    ) {
+        Ok(loaded) => loaded,
+        Err(error) => {
+            problems.push(Problem::LoadFailed(error.to_string()));
+            return AssetReport {
+                path: path.to_path_buf(),
+                problems,
+            };
+        }
+    };
+
+    match obj_materials {
+        Ok(obj_materials) => {
+            // textures are looked up next to the obj file, as Object::load_from does
+            let containing_folder = path.parent().unwrap_or_else(|| Path::new(""));
+            for mat in obj_materials {
+                let texture_path = containing_folder.join(&mat.diffuse_texture);
+                if mat.diffuse_texture.is_empty() || !texture_path.is_file() {
+                    problems.push(Problem::MissingTexture {
+                        material: mat.name,
+                        path: texture_path,
+                    });
+                }
+            }
+        }
+        Err(error) => problems.push(Problem::MaterialsFailed(error.to_string())),
+    }
+
+    let mut model_bounds = bounds::Aabb::empty();
+    for m in &obj_models {
+        let positions: Vec<Point3<f32>> = m
+            .mesh
+            .positions
+            .chunks_exact(3)
+            .map(|p| Point3::new(p[0], p[1], p[2]))
+            .collect();
+        model_bounds = model_bounds.union(&bounds::Aabb::from_points(positions.iter().copied()));
+
+        if m.mesh.normals.is_empty() {
+            problems.push(Problem::NoNormals {
+                mesh: m.name.clone(),
+            });
+        }
+        if m.mesh.texcoords.is_empty() {
+            problems.push(Problem::NoTexCoords {
+                mesh: m.name.clone(),
+            });
+        }
+
+        let degenerate_count = m
+            .mesh
+            .indices
+            .chunks_exact(3)
+            .filter(|t| {
+                match (
+                    positions.get(t[0] as usize),
+                    positions.get(t[1] as usize),
+                    positions.get(t[2] as usize),
+                ) {
+                    (Some(a), Some(b), Some(c)) => is_degenerate_triangle(*a, *b, *c),
+                    // out of range indices can't make a valid triangle either
+                    _ => true,
+                }
+            })
+            .count();
+        if degenerate_count > 0 {
+            problems.push(Problem::DegenerateTriangles {
+                mesh: m.name.clone(),
+                count: degenerate_count,
+            });
+        }
+    }
+
+    if !model_bounds.is_empty() {
+        let size = model_bounds.max - model_bounds.min;
+        if size.x > HUGE_BOUNDS_SIZE || size.y > HUGE_BOUNDS_SIZE || size.z > HUGE_BOUNDS_SIZE {
+            problems.push(Problem::HugeBounds { size });
+        }
+    }
+
+    AssetReport {
+        path: path.to_path_buf(),
+        problems,
+    }
+}
+
+// Check every obj file found in a directory and its sub directories
+pub fn validate_dir<P: AsRef<Path>>(dir: P) -> std::io::Result<Vec<AssetReport>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.as_ref().to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = std::fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        entries.sort();
+        for path in entries {
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("obj"))
+            {
+                files.push(path);
+            }
+        }
+    }
+    // files are decoded and checked in parallel
+    Ok(jobs::map_jobs(files, validate_file))
+}
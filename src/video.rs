@@ -0,0 +1,126 @@
+use crate::texture;
+use anyhow::*;
+use image::AnimationDecoder;
+use std::path::Path;
+use std::time::Duration;
+
+// Decoded frames played by a VideoTexture. Decoder backends implement it to
+// feed their frames as 8 bits rgba pixels.
+pub trait FrameSource {
+    fn dimensions(&self) -> (u32, u32);
+
+    // Move the playback forward, returns the frame to show if it changed
+    fn advance(&mut self, dt: Duration) -> Option<&[u8]>;
+}
+
+impl<S: FrameSource + ?Sized> FrameSource for Box<S> {
+    fn dimensions(&self) -> (u32, u32) {
+        (**self).dimensions()
+    }
+
+    fn advance(&mut self, dt: Duration) -> Option<&[u8]> {
+        (**self).advance(dt)
+    }
+}
+
+// Frames of an animated GIF, played in a loop
+pub struct GifSource {
+    frames: Vec<(image::RgbaImage, Duration)>,
+    current: usize,
+    elapsed: Duration,
+    // last frame returned by advance
+    shown: Option<usize>,
+}
+
+impl GifSource {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = std::io::BufReader::new(std::fs::File::open(path)?);
+        let frames: Vec<_> = image::codecs::gif::GifDecoder::new(file)?
+            .into_frames()
+            .collect_frames()?
+            .into_iter()
+            .map(|frame| {
+                let (numer, denom) = frame.delay().numer_denom_ms();
+                let delay = Duration::from_secs_f64(numer as f64 / denom.max(1) as f64 / 1000.0);
+                // like browsers, show frames without delay for a tenth of a second
+                let delay = if delay.is_zero() {
+                    Duration::from_millis(100)
+                } else {
+                    delay
+                };
+                (frame.into_buffer(), delay)
+            })
+            .collect();
+        ensure!(!frames.is_empty(), "gif has no frame");
+
+        Ok(Self {
+            frames,
+            current: 0,
+            elapsed: Duration::ZERO,
+            shown: None,
+        })
+    }
+}
+
+impl FrameSource for GifSource {
+    fn dimensions(&self) -> (u32, u32) {
+        self.frames[0].0.dimensions()
+    }
+
+    fn advance(&mut self, dt: Duration) -> Option<&[u8]> {
+        self.elapsed += dt;
+        // skip the frames that were entirely covered by dt
+        while self.elapsed >= self.frames[self.current].1 {
+            self.elapsed -= self.frames[self.current].1;
+            self.current = (self.current + 1) % self.frames.len();
+        }
+
+        if self.shown != Some(self.current) {
+            self.shown = Some(self.current);
+            Some(self.frames[self.current].0.as_raw())
+        } else {
+            None
+        }
+    }
+}
+
+// Plays a frame source into a texture. Any texture of the same size can be the
+// target, e.g. the diffuse texture of one of the materials of a model.
+// Lens::add_video plays it in a material of a lens scene.
+pub struct VideoTexture<S: FrameSource> {
+    pub source: S,
+}
+
+// Video of any source, as the scene keeps them
+pub(crate) type BoxedVideo = VideoTexture<Box<dyn FrameSource>>;
+
+impl<S: FrameSource> VideoTexture<S> {
+    pub fn new(source: S) -> Self {
+        Self { source }
+    }
+
+    // Texture matching the size of the frames, to be assigned to a material
+    pub fn create_texture(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        label: Option<&str>,
+    ) -> Result<texture::Texture> {
+        let (width, height) = self.source.dimensions();
+        let black = vec![0; (4 * width * height) as usize];
+        texture::Texture::from_rgba8(device, queue, &black, (width, height), label)
+    }
+
+    // Upload the next frame to the texture, once per rendered frame
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        target: &texture::Texture,
+        dt: Duration,
+    ) -> Result<()> {
+        if let Some(frame) = self.source.advance(dt) {
+            target.write_rgba8(queue, frame)?;
+        }
+        Ok(())
+    }
+}